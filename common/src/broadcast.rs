@@ -7,21 +7,25 @@ use std::{
 use log::*;
 use parking_lot::{Condvar, Mutex};
 
-use crate::{BROADCAST_ADDRESS, BROADCAST_BIND_ADDRESS};
-
 pub struct BroadcastTask {
     wifi_status: Arc<(Mutex<bool>, Condvar)>,
     discovery_tx: crossbeam_channel::Sender<SocketAddr>,
+    bind_address: String,
+    broadcast_address: String,
 }
 
 impl BroadcastTask {
     pub fn new(
         wifi_status: Arc<(Mutex<bool>, Condvar)>,
         discovery_tx: crossbeam_channel::Sender<SocketAddr>,
+        bind_address: String,
+        broadcast_address: String,
     ) -> Self {
         BroadcastTask {
             wifi_status,
             discovery_tx,
+            bind_address,
+            broadcast_address,
         }
     }
 }
@@ -30,6 +34,8 @@ pub fn init_task(task: BroadcastTask) -> anyhow::Result<()> {
     let BroadcastTask {
         wifi_status,
         discovery_tx,
+        bind_address,
+        broadcast_address,
     } = task;
 
     // TODO: convert this task to a machine state that can be re allocated, maybe two broadcast to be M:M
@@ -44,7 +50,7 @@ pub fn init_task(task: BroadcastTask) -> anyhow::Result<()> {
     drop(started);
 
     // TODO: make a multicast alternative
-    let socket = UdpSocket::bind(BROADCAST_BIND_ADDRESS).unwrap();
+    let socket = UdpSocket::bind(bind_address.as_str()).unwrap();
     socket.set_broadcast(true).unwrap();
 
     info!("[discovery_task]: Listening to {:?}", socket.local_addr());
@@ -63,11 +69,11 @@ pub fn init_task(task: BroadcastTask) -> anyhow::Result<()> {
             }
             info!(
                 "[sender_task]: sending udp packet - {:?}",
-                BROADCAST_ADDRESS
+                broadcast_address
             );
             socket_tx
                 .read()
-                .send_to("hello".as_bytes(), BROADCAST_ADDRESS)
+                .send_to("hello".as_bytes(), broadcast_address.as_str())
                 .unwrap();
 
             std::thread::sleep(Duration::from_millis(1000));
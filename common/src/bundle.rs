@@ -0,0 +1,210 @@
+use std::fmt;
+
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use jojo_common::{device::Device, network::NetworkCredentials};
+use log::*;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub use logic::bundle::{BundleError, BUNDLE_VERSION, CALIBRATION_FLASH_LIMIT};
+
+use crate::{
+    calibration::{self, Calibration},
+    device::{self, DEVICE_FLASH_LIMIT},
+    input::config::{self as input_config, InputConfig},
+    network,
+    settings::{self, SettingsUpdate},
+    store,
+};
+
+/// The whole configuration of a device, used to back it up and to clone it to other units.
+/// Sections left out of an imported bundle keep the value already stored in flash.
+#[derive(Serialize, Deserialize)]
+pub struct ConfigBundle {
+    version: u32,
+    // The device stores a single network for now, only the first one is imported
+    #[serde(default)]
+    networks: Vec<NetworkCredentials>,
+    #[serde(default)]
+    device: Option<Device>,
+    // Applied like a settings update, a bundle without `auth_key` keeps the stored one
+    #[serde(default)]
+    server_settings: Option<SettingsUpdate>,
+    #[serde(default)]
+    calibration: Option<Calibration>,
+    #[serde(default)]
+    input: Option<InputConfig>,
+}
+
+// Passwords of the networks are left out of the logs
+impl fmt::Debug for ConfigBundle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let networks: Vec<_> = self.networks.iter().map(network::Redacted).collect();
+
+        f.debug_struct("ConfigBundle")
+            .field("version", &self.version)
+            .field("networks", &networks)
+            .field("device", &self.device)
+            .field("server_settings", &self.server_settings)
+            .field("calibration", &self.calibration)
+            .field("input", &self.input)
+            .finish()
+    }
+}
+
+impl ConfigBundle {
+    pub fn new(
+        networks: Vec<NetworkCredentials>,
+        device: Option<Device>,
+        server_settings: Option<SettingsUpdate>,
+        calibration: Option<Calibration>,
+        input: Option<InputConfig>,
+    ) -> Self {
+        ConfigBundle {
+            version: BUNDLE_VERSION,
+            networks,
+            device,
+            server_settings,
+            calibration,
+            input,
+        }
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn networks(&self) -> &[NetworkCredentials] {
+        &self.networks
+    }
+
+    pub fn device(&self) -> Option<&Device> {
+        self.device.as_ref()
+    }
+
+    pub fn server_settings(&self) -> Option<&SettingsUpdate> {
+        self.server_settings.as_ref()
+    }
+
+    pub fn calibration(&self) -> Option<&Calibration> {
+        self.calibration.as_ref()
+    }
+
+    pub fn input(&self) -> Option<&InputConfig> {
+        self.input.as_ref()
+    }
+
+    /// Checks every section so an import either writes all of them or none
+    pub fn validate(&self) -> Result<(), BundleError> {
+        if self.version != BUNDLE_VERSION {
+            return Err(BundleError::UnsupportedVersion(self.version));
+        }
+
+        for credentials in &self.networks {
+            network::validate_credentials(
+                &credentials.ssid.to_string(),
+                &credentials.password.to_string(),
+            )
+            .map_err(BundleError::Credentials)?;
+        }
+
+        if let Some(device) = &self.device {
+            device::validate(device).map_err(BundleError::Device)?;
+
+            let size = store::encoded_len(device).unwrap_or(usize::MAX);
+            if size > DEVICE_FLASH_LIMIT {
+                return Err(BundleError::DeviceTooLarge(size));
+            }
+        }
+
+        if let Some(server_settings) = &self.server_settings {
+            server_settings.validate().map_err(BundleError::Settings)?;
+        }
+
+        if let Some(calibration) = &self.calibration {
+            let size = store::encoded_len(calibration).unwrap_or(usize::MAX);
+            if size > CALIBRATION_FLASH_LIMIT {
+                return Err(BundleError::CalibrationTooLarge(size));
+            }
+        }
+
+        if let Some(input) = &self.input {
+            // Buttons are checked against the device of the bundle, the stored one may be replaced
+            let known: Option<Vec<Uuid>> = self
+                .device
+                .as_ref()
+                .map(|device| device.buttons().iter().map(|button| button.id()).collect());
+
+            input
+                .validate(known.as_deref())
+                .map_err(BundleError::Input)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds a bundle with everything stored in flash. The networks and the server auth key are left
+/// out unless `include_secrets` is set, importing such a bundle keeps the ones of the target.
+pub fn export(
+    nvs_namespace: &EspNvs<NvsDefault>,
+    include_secrets: bool,
+) -> anyhow::Result<ConfigBundle> {
+    let mut server_settings = SettingsUpdate::from_settings(settings::load(nvs_namespace));
+    let mut networks = Vec::new();
+
+    if include_secrets {
+        networks.extend(store::load::<NetworkCredentials>(nvs_namespace));
+    } else {
+        server_settings = server_settings.without_auth_key();
+    }
+
+    let device = store::load::<Device>(nvs_namespace);
+    let calibration = calibration::load(nvs_namespace);
+
+    Ok(ConfigBundle::new(
+        networks,
+        device,
+        Some(server_settings),
+        (!calibration.is_empty()).then_some(calibration),
+        Some(input_config::load(nvs_namespace)),
+    ))
+}
+
+/// Validates the bundle and writes the sections it carries, a restart is needed to apply them
+pub fn import(nvs_namespace: &mut EspNvs<NvsDefault>, bundle: &ConfigBundle) -> anyhow::Result<()> {
+    bundle.validate()?;
+
+    if bundle.networks.len() > 1 {
+        warn!(
+            "[bundle]: {} networks in bundle, only the first one is stored",
+            bundle.networks.len()
+        );
+    }
+
+    if let Some(credentials) = bundle.networks.first() {
+        store::save(nvs_namespace, credentials)?;
+    }
+
+    if let Some(device) = &bundle.device {
+        store::save(nvs_namespace, device)?;
+    }
+
+    if let Some(update) = &bundle.server_settings {
+        let server_settings = update.clone().apply(&settings::load(nvs_namespace));
+
+        settings::save(nvs_namespace, &server_settings)?;
+    }
+
+    if let Some(calibration) = &bundle.calibration {
+        calibration::save(nvs_namespace, calibration)?;
+    }
+
+    if let Some(input) = &bundle.input {
+        input_config::save(nvs_namespace, input)?;
+    }
+
+    info!("[bundle]: configuration imported");
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bundle::CALIBRATION_FLASH_LIMIT,
+    store::{self, Migration, Schema},
+    CALIBRATION_TAG,
+};
+
+/// Resting ADC reads of the analog inputs, keyed by input name (e.g. `stick_x`).
+/// Tasks measure them once and reuse them after a restart instead of calibrating again.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Calibration {
+    zero_reads: BTreeMap<String, u16>,
+}
+
+impl Calibration {
+    pub fn zero_read(&self, input: &str) -> Option<u16> {
+        self.zero_reads.get(input).copied()
+    }
+
+    pub fn set_zero_read(&mut self, input: &str, read: u16) {
+        self.zero_reads.insert(input.to_string(), read);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zero_reads.is_empty()
+    }
+}
+
+impl Schema for Calibration {
+    const TAG: &'static str = CALIBRATION_TAG;
+    const VERSION: u16 = 1;
+    const MAX_SIZE: usize = CALIBRATION_FLASH_LIMIT;
+
+    fn migrations() -> &'static [Migration] {
+        &[store::from_legacy]
+    }
+}
+
+/// Reads the calibration from flash, empty when nothing was measured yet
+pub fn load(nvs_namespace: &EspNvs<NvsDefault>) -> Calibration {
+    store::load(nvs_namespace).unwrap_or_default()
+}
+
+pub fn save(
+    nvs_namespace: &mut EspNvs<NvsDefault>,
+    calibration: &Calibration,
+) -> anyhow::Result<()> {
+    store::save(nvs_namespace, calibration)
+}
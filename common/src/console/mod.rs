@@ -0,0 +1,157 @@
+use std::{io::Read, sync::Arc, time::Duration};
+
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::{esp_get_free_heap_size, esp_timer_get_time},
+};
+use jojo_common::device::Device;
+use log::*;
+use parking_lot::Mutex;
+
+use crate::{network, secrets, store, ALL_TAGS, CALIBRATION_TAG, NETWORK_TAG, OTP_TOKEN_TAG};
+
+pub use logic::console::{command, handler, line};
+
+use line::{LineBuffer, LineEvent};
+
+const MAX_LINE_LEN: usize = 256;
+const RESTART_DELAY: Duration = Duration::from_millis(200);
+
+pub struct ConsoleTask {
+    mode: &'static str,
+    nvs_namespace: Arc<Mutex<EspNvs<NvsDefault>>>,
+}
+
+impl ConsoleTask {
+    pub fn new(mode: &'static str, nvs_namespace: Arc<Mutex<EspNvs<NvsDefault>>>) -> Self {
+        ConsoleTask {
+            mode,
+            nvs_namespace,
+        }
+    }
+}
+
+struct EspBackend {
+    mode: &'static str,
+    nvs_namespace: Arc<Mutex<EspNvs<NvsDefault>>>,
+}
+
+impl handler::Backend for EspBackend {
+    fn mode(&self) -> &'static str {
+        self.mode
+    }
+
+    fn uptime_secs(&self) -> u64 {
+        (unsafe { esp_timer_get_time() } / 1_000_000) as u64
+    }
+
+    fn free_heap(&self) -> u32 {
+        unsafe { esp_get_free_heap_size() }
+    }
+
+    fn credentials_ssid(&self) -> anyhow::Result<Option<String>> {
+        let credentials =
+            store::load::<jojo_common::network::NetworkCredentials>(&self.nvs_namespace.lock());
+
+        Ok(credentials.map(|credentials| credentials.ssid.to_string()))
+    }
+
+    fn set_credentials(&mut self, ssid: &str, password: &str) -> anyhow::Result<()> {
+        let credentials = network::credentials(ssid, password)?;
+
+        store::save(&mut self.nvs_namespace.lock(), &credentials)
+    }
+
+    fn clear_credentials(&mut self) -> anyhow::Result<()> {
+        store::remove(&mut self.nvs_namespace.lock(), NETWORK_TAG)?;
+
+        Ok(())
+    }
+
+    fn device_json(&self) -> anyhow::Result<Option<String>> {
+        match store::load::<Device>(&self.nvs_namespace.lock()) {
+            Some(device) => Ok(Some(serde_json::to_string_pretty(&device)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn ap_password(&self) -> anyhow::Result<Option<String>> {
+        Ok(secrets::stored_ap_password(&self.nvs_namespace.lock()))
+    }
+
+    fn otp_token(&self) -> anyhow::Result<Option<String>> {
+        Ok(secrets::stored_otp_token(&self.nvs_namespace.lock()))
+    }
+
+    fn enable_auth(&mut self) -> anyhow::Result<String> {
+        secrets::otp_token(&mut self.nvs_namespace.lock())
+    }
+
+    fn disable_auth(&mut self) -> anyhow::Result<()> {
+        store::remove(&mut self.nvs_namespace.lock(), OTP_TOKEN_TAG)?;
+
+        Ok(())
+    }
+
+    fn clear_calibration(&mut self) -> anyhow::Result<()> {
+        store::remove(&mut self.nvs_namespace.lock(), CALIBRATION_TAG)?;
+
+        Ok(())
+    }
+
+    fn erase(&mut self) -> anyhow::Result<()> {
+        let mut nvs_namespace = self.nvs_namespace.lock();
+
+        for tag in ALL_TAGS {
+            store::remove(&mut nvs_namespace, tag)?;
+        }
+
+        Ok(())
+    }
+
+    fn reboot(&mut self) {
+        crate::restart_after(RESTART_DELAY);
+    }
+}
+
+pub fn init_task(task: ConsoleTask) {
+    let ConsoleTask {
+        mode,
+        nvs_namespace,
+    } = task;
+
+    let mut backend = EspBackend {
+        mode,
+        nvs_namespace,
+    };
+
+    let mut line_buffer = LineBuffer::new(MAX_LINE_LEN);
+    let mut stdin = std::io::stdin();
+    let mut byte = [0u8; 1];
+
+    info!("[console_task]: ready, type help");
+
+    loop {
+        // The default console does not block on reads, an empty read just means nothing was typed
+        match stdin.read(&mut byte) {
+            Ok(1) => {
+                let Some(event) = line_buffer.push(byte[0]) else {
+                    continue;
+                };
+
+                let output = match event {
+                    LineEvent::Line(line) => match command::parse(&line) {
+                        Ok(command) => handler::execute(command, &mut backend),
+                        Err(err) => format!("error: {}", err),
+                    },
+                    LineEvent::Overflow => {
+                        format!("error: line longer than {} characters", MAX_LINE_LEN)
+                    }
+                };
+
+                println!("{}", output);
+            }
+            _ => std::thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
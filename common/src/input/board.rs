@@ -0,0 +1,163 @@
+use esp_idf_hal::{
+    gpio::{
+        AnyIOPin, Gpio0, Gpio1, Gpio10, Gpio11, Gpio12, Gpio13, Gpio16, Gpio17, Gpio2, Gpio6,
+        Gpio7, Gpio8, Gpio9, Pull,
+    },
+    i2c::{I2cConfig, I2cDriver, I2C0},
+    pcnt::PCNT0,
+    units::FromValueType,
+};
+use jojo_common::device::Device;
+use log::*;
+
+use super::{
+    encoder::{self, EncoderPins},
+    expander_config, expander_interrupt_enabled, matrix_enabled, ButtonInput, ButtonSource,
+    ExpanderBus, MatrixPins,
+};
+
+const EXPANDER_I2C_BAUDRATE_KHZ: u32 = 400;
+
+/// Peripherals the inputs are wired to, the same on every client board
+pub struct InputPeripherals {
+    /// Single pin buttons, pulled down and up
+    pub gpio0: Gpio0,
+    pub gpio6: Gpio6,
+    /// Matrix rows
+    pub gpio7: Gpio7,
+    pub gpio8: Gpio8,
+    pub gpio9: Gpio9,
+    /// Matrix columns
+    pub gpio10: Gpio10,
+    pub gpio11: Gpio11,
+    pub gpio12: Gpio12,
+    /// Expander SDA, SCL and interrupt line
+    pub gpio1: Gpio1,
+    pub gpio2: Gpio2,
+    pub gpio13: Gpio13,
+    pub i2c0: I2C0,
+    /// Encoder channels A and B
+    pub gpio16: Gpio16,
+    pub gpio17: Gpio17,
+    pub pcnt0: PCNT0,
+}
+
+/// Inputs enabled in the `.env` file, with the pins they use
+pub struct InputPins {
+    gpios: Vec<(AnyIOPin, Pull)>,
+    pub matrix: Option<MatrixPins>,
+    pub expander: Option<ExpanderBus>,
+    pub encoder: Option<EncoderPins>,
+}
+
+impl InputPins {
+    pub fn new(peripherals: InputPeripherals) -> anyhow::Result<Self> {
+        let gpios: Vec<(AnyIOPin, Pull)> = vec![
+            (peripherals.gpio0.into(), Pull::Down),
+            (peripherals.gpio6.into(), Pull::Up),
+        ];
+
+        let matrix = matrix_enabled().then(|| {
+            MatrixPins::new(
+                vec![
+                    peripherals.gpio7.into(),
+                    peripherals.gpio8.into(),
+                    peripherals.gpio9.into(),
+                ],
+                vec![
+                    peripherals.gpio10.into(),
+                    peripherals.gpio11.into(),
+                    peripherals.gpio12.into(),
+                ],
+            )
+        });
+
+        let expander = match expander_config() {
+            Some((kind, address)) => {
+                let config = I2cConfig::new().baudrate(EXPANDER_I2C_BAUDRATE_KHZ.kHz().into());
+                let i2c = I2cDriver::new(
+                    peripherals.i2c0,
+                    peripherals.gpio1,
+                    peripherals.gpio2,
+                    &config,
+                )?;
+
+                Some(ExpanderBus::new(
+                    i2c,
+                    kind,
+                    address,
+                    expander_interrupt_enabled().then(|| peripherals.gpio13.into()),
+                ))
+            }
+            None => None,
+        };
+
+        let encoder = encoder::enabled().then(|| {
+            EncoderPins::new(
+                peripherals.gpio16.into(),
+                peripherals.gpio17.into(),
+                encoder::uses_pcnt().then_some(peripherals.pcnt0),
+            )
+        });
+
+        Ok(InputPins {
+            gpios,
+            matrix,
+            expander,
+            encoder,
+        })
+    }
+
+    /// Buttons of the device, read from the single pins first, then from the pins of the
+    /// expander and then from the matrix, key 0 first
+    pub fn button_inputs(&mut self, device: &Device) -> Vec<ButtonInput> {
+        let mut gpios = std::mem::take(&mut self.gpios);
+        let mut expander_pins = (0..self.expander.as_ref().map_or(0, ExpanderBus::pins))
+            .rev()
+            .collect::<Vec<_>>();
+        let mut matrix_keys = (0..self.matrix.as_ref().map_or(0, MatrixPins::keys))
+            .rev()
+            .collect::<Vec<_>>();
+
+        let mut actions_map = device.actions_map().clone();
+        let buttons = device.buttons().clone();
+
+        info!("[buttons]: {:?}", buttons);
+        info!("[actions]: {:?}", actions_map);
+
+        let mut inputs = Vec::new();
+
+        for button in buttons {
+            info!("[button]: {:?}, gpio_len: {:?}", button, gpios.len());
+
+            let source = if let Some((pin, pull)) = gpios.pop() {
+                ButtonSource::Pin(pin, pull)
+            } else if let Some(pin) = expander_pins.pop() {
+                ButtonSource::Expander(pin)
+            } else {
+                ButtonSource::Matrix(
+                    matrix_keys
+                        .pop()
+                        .expect("cannot unwrap gpio, expander pin or matrix key"),
+                )
+            };
+            let action = actions_map.remove(&button.id()).expect(
+                format!(
+                    "cannot unwrap button.id: {:?}, actions: {:?}",
+                    actions_map,
+                    button.id()
+                )
+                .as_str(),
+            );
+
+            inputs.push(ButtonInput::new(
+                button.id(),
+                source,
+                action,
+                button.mode().clone(),
+            ));
+        }
+
+        inputs
+    }
+}
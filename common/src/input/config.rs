@@ -0,0 +1,430 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use jojo_common::{button::ButtonAction, gamepad::Axis};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    gesture::GestureTiming,
+    layer::LayerKey,
+    macros::MacroStep,
+    quadrature::{Acceleration, StepCounter},
+    validate::{self, InputError, INPUT_FLASH_LIMIT},
+};
+use crate::{
+    store::{self, Migration, Schema},
+    INPUT_TAG,
+};
+
+// Long enough for the usual tactile switches, short enough to not be felt
+const DEFAULT_DEBOUNCE_MS: u16 = 20;
+// Presses this close count as pressed together
+const DEFAULT_COMBO_WINDOW_MS: u16 = 50;
+const DEFAULT_TAP_GAP_MS: u16 = 250;
+const DEFAULT_LONG_PRESS_MS: u16 = 600;
+const DEFAULT_REPEAT_INTERVAL_MS: u16 = 100;
+// Faster repeats would flood the websocket
+const MIN_REPEAT_INTERVAL_MS: u16 = 20;
+// Most encoders rest on a detent once per quadrature cycle
+const DEFAULT_COUNTS_PER_DETENT: u8 = 4;
+const DEFAULT_FAST_STEP_MS: u16 = 30;
+const DEFAULT_MAX_MULTIPLIER: u8 = 4;
+
+/// Interval between repeated actions, clamped to what the websocket keeps up with
+pub fn repeat_interval(interval_ms: u16) -> Duration {
+    Duration::from_millis(interval_ms.max(MIN_REPEAT_INTERVAL_MS).into())
+}
+
+/// Extra action lists of a button, the actions of the device are sent on a single click.
+/// Gestures that are left empty are not detected, so they do not delay the others.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GestureConfig {
+    /// Actions of 2, 3, ... taps in a row, the first list is the double click
+    pub taps: Vec<Vec<ButtonAction>>,
+    pub long_press: Vec<ButtonAction>,
+    /// Sent again every repeat interval while the button stays held after a long press
+    pub repeat: Vec<ButtonAction>,
+    pub tap_gap_ms: u16,
+    pub long_press_ms: u16,
+    pub repeat_interval_ms: u16,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        GestureConfig {
+            taps: vec![],
+            long_press: vec![],
+            repeat: vec![],
+            tap_gap_ms: DEFAULT_TAP_GAP_MS,
+            long_press_ms: DEFAULT_LONG_PRESS_MS,
+            repeat_interval_ms: DEFAULT_REPEAT_INTERVAL_MS,
+        }
+    }
+}
+
+impl GestureConfig {
+    pub fn timing(&self) -> GestureTiming {
+        GestureTiming {
+            tap_gap: Duration::from_millis(self.tap_gap_ms.into()),
+            long_press: Duration::from_millis(self.long_press_ms.into()),
+            repeat_interval: (!self.repeat.is_empty())
+                .then(|| repeat_interval(self.repeat_interval_ms)),
+            max_taps: u8::try_from(self.taps.len() + 1).unwrap_or(u8::MAX),
+            detect_long_press: !self.long_press.is_empty() || !self.repeat.is_empty(),
+        }
+    }
+
+    /// Actions of a gesture, the single click is left to the caller
+    pub fn actions(&self, taps: u8) -> &[ButtonAction] {
+        usize::from(taps)
+            .checked_sub(2)
+            .and_then(|index| self.taps.get(index))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+/// Modes the device does not know about, they replace its hold and click modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputMode {
+    /// One press sends the down state of each action and the next press the up state
+    Toggle,
+    /// Clicks every action again each interval while the button is held
+    Turbo { interval_ms: u16 },
+}
+
+/// Tuning of a single button, fields left empty use the defaults of InputConfig
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ButtonConfig {
+    pub debounce_ms: Option<u16>,
+    /// Replaces the hold and click modes of the device for this button
+    pub gestures: Option<GestureConfig>,
+    /// Ignored when the button has gestures or a macro
+    pub mode: Option<InputMode>,
+    /// Steps run on press, ignored when the button has gestures
+    #[serde(rename = "macro")]
+    pub macro_steps: Option<Vec<MacroStep>>,
+    /// Makes the button switch the layer of the others, it sends no actions then
+    pub layer: Option<LayerKey>,
+}
+
+/// Buttons pressed together, sending their own actions instead of the ones of each button
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComboConfig {
+    pub buttons: Vec<Uuid>,
+    pub actions: Vec<ButtonAction>,
+}
+
+/// Actions that replace the ones of the device while the layer is active,
+/// buttons left out keep the actions of the device
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LayerConfig {
+    pub name: String,
+    pub actions: BTreeMap<Uuid, Vec<ButtonAction>>,
+}
+
+/// What a rotary encoder sends on each step
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EncoderOutput {
+    /// Actions sent once per step in each direction, the scroll actions of the mouse
+    Actions {
+        clockwise: Vec<ButtonAction>,
+        counter_clockwise: Vec<ButtonAction>,
+    },
+    /// Moves a gamepad axis by `scale` per step, clockwise up
+    Axis { axis: Axis, scale: i32 },
+}
+
+/// Steps turned faster than `fast_step_ms` apart are multiplied, up to `max_multiplier`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccelerationConfig {
+    pub fast_step_ms: u16,
+    pub max_multiplier: u8,
+}
+
+impl Default for AccelerationConfig {
+    fn default() -> Self {
+        AccelerationConfig {
+            fast_step_ms: DEFAULT_FAST_STEP_MS,
+            max_multiplier: DEFAULT_MAX_MULTIPLIER,
+        }
+    }
+}
+
+/// Rotary encoder of the device, nothing is sent until it has an output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncoderConfig {
+    pub output: Option<EncoderOutput>,
+    pub counts_per_detent: u8,
+    /// Detents turned for each step sent
+    pub detents_per_step: u8,
+    pub acceleration: Option<AccelerationConfig>,
+    /// Swaps clockwise and counter clockwise
+    pub reverse: bool,
+}
+
+impl Default for EncoderConfig {
+    fn default() -> Self {
+        EncoderConfig {
+            output: None,
+            counts_per_detent: DEFAULT_COUNTS_PER_DETENT,
+            detents_per_step: 1,
+            acceleration: None,
+            reverse: false,
+        }
+    }
+}
+
+impl EncoderConfig {
+    pub fn steps(&self) -> StepCounter {
+        let acceleration = self.acceleration.map(|acceleration| Acceleration {
+            fast_us: u64::from(acceleration.fast_step_ms) * 1000,
+            max_multiplier: acceleration.max_multiplier,
+        });
+
+        StepCounter::new(self.counts_per_detent, self.detents_per_step, acceleration)
+    }
+}
+
+/// How the buttons of the device are read, keyed by the button id of the device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputConfig {
+    pub debounce_ms: u16,
+    pub buttons: BTreeMap<Uuid, ButtonConfig>,
+    pub combo_window_ms: u16,
+    pub combos: Vec<ComboConfig>,
+    /// Layers from 1 on, layer 0 is the actions map of the device
+    pub layers: Vec<LayerConfig>,
+    pub encoder: Option<EncoderConfig>,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            buttons: BTreeMap::new(),
+            combo_window_ms: DEFAULT_COMBO_WINDOW_MS,
+            combos: vec![],
+            layers: vec![],
+            encoder: None,
+        }
+    }
+}
+
+impl InputConfig {
+    pub fn button(&self, id: &Uuid) -> Option<&ButtonConfig> {
+        self.buttons.get(id)
+    }
+
+    pub fn debounce(&self, id: &Uuid) -> Duration {
+        let debounce_ms = self
+            .button(id)
+            .and_then(|button| button.debounce_ms)
+            .unwrap_or(self.debounce_ms);
+
+        Duration::from_millis(debounce_ms.into())
+    }
+
+    pub fn combo_window(&self) -> Duration {
+        Duration::from_millis(self.combo_window_ms.into())
+    }
+
+    /// Number of layers, the one of the device included
+    pub fn layer_count(&self) -> usize {
+        self.layers.len() + 1
+    }
+
+    /// Checks the combos and layers can be used, `known` are the buttons of the device when
+    /// there is one to check them against
+    pub fn validate(&self, known: Option<&[Uuid]>) -> Result<(), InputError> {
+        let size = store::encoded_len(self).unwrap_or(usize::MAX);
+        if size > INPUT_FLASH_LIMIT {
+            return Err(InputError::TooLarge(size));
+        }
+
+        for (index, combo) in self.combos.iter().enumerate() {
+            validate::validate_combo(index, &combo.buttons, known)?;
+        }
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            let buttons: Vec<Uuid> = layer.actions.keys().copied().collect();
+            validate::validate_layer(index + 1, &buttons, known)?;
+        }
+
+        for (id, button) in &self.buttons {
+            if let Some(key) = button.layer {
+                validate::validate_layer_key(*id, key, self.layer_count())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Version 1 had no gestures
+fn from_v1(payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let (debounce_ms, buttons): (u16, BTreeMap<Uuid, Option<u16>>) =
+        bincode::deserialize(&payload)?;
+
+    let buttons: BTreeMap<Uuid, (Option<u16>, Option<GestureConfig>)> = buttons
+        .into_iter()
+        .map(|(id, debounce_ms)| (id, (debounce_ms, None)))
+        .collect();
+
+    Ok(bincode::serialize(&(debounce_ms, buttons))?)
+}
+
+// Layout of a button from version 3 to 4
+type ButtonConfigV3 = (Option<u16>, Option<GestureConfig>, Option<InputMode>);
+
+/// Version 2 had no input modes
+fn from_v2(payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let (debounce_ms, buttons): (u16, BTreeMap<Uuid, (Option<u16>, Option<GestureConfig>)>) =
+        bincode::deserialize(&payload)?;
+
+    let buttons: BTreeMap<Uuid, ButtonConfigV3> = buttons
+        .into_iter()
+        .map(|(id, (debounce_ms, gestures))| {
+            let button: ButtonConfigV3 = (debounce_ms, gestures, None);
+            (id, button)
+        })
+        .collect();
+
+    Ok(bincode::serialize(&(debounce_ms, buttons))?)
+}
+
+/// Version 3 had no combos
+fn from_v3(payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let (debounce_ms, buttons): (u16, BTreeMap<Uuid, ButtonConfigV3>) =
+        bincode::deserialize(&payload)?;
+
+    let combos: Vec<ComboConfig> = vec![];
+
+    Ok(bincode::serialize(&(
+        debounce_ms,
+        buttons,
+        DEFAULT_COMBO_WINDOW_MS,
+        combos,
+    ))?)
+}
+
+/// Version 4 had no macros
+fn from_v4(payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let (debounce_ms, buttons, combo_window_ms, combos): (
+        u16,
+        BTreeMap<Uuid, ButtonConfigV3>,
+        u16,
+        Vec<ComboConfig>,
+    ) = bincode::deserialize(&payload)?;
+
+    let buttons: BTreeMap<Uuid, ButtonConfigV5> = buttons
+        .into_iter()
+        .map(|(id, (debounce_ms, gestures, mode))| (id, (debounce_ms, gestures, mode, None)))
+        .collect();
+
+    Ok(bincode::serialize(&(
+        debounce_ms,
+        buttons,
+        combo_window_ms,
+        combos,
+    ))?)
+}
+
+// Layout of a button in version 5
+type ButtonConfigV5 = (
+    Option<u16>,
+    Option<GestureConfig>,
+    Option<InputMode>,
+    Option<Vec<MacroStep>>,
+);
+
+/// Version 5 had no layers
+fn from_v5(payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let (debounce_ms, buttons, combo_window_ms, combos): (
+        u16,
+        BTreeMap<Uuid, ButtonConfigV5>,
+        u16,
+        Vec<ComboConfig>,
+    ) = bincode::deserialize(&payload)?;
+
+    let buttons: BTreeMap<Uuid, ButtonConfig> = buttons
+        .into_iter()
+        .map(|(id, (debounce_ms, gestures, mode, macro_steps))| {
+            let button = ButtonConfig {
+                debounce_ms,
+                gestures,
+                mode,
+                macro_steps,
+                layer: None,
+            };
+            (id, button)
+        })
+        .collect();
+    let layers: Vec<LayerConfig> = vec![];
+
+    Ok(bincode::serialize(&(
+        debounce_ms,
+        buttons,
+        combo_window_ms,
+        combos,
+        layers,
+    ))?)
+}
+
+/// Version 6 had no encoder
+fn from_v6(payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let (debounce_ms, buttons, combo_window_ms, combos, layers): (
+        u16,
+        BTreeMap<Uuid, ButtonConfig>,
+        u16,
+        Vec<ComboConfig>,
+        Vec<LayerConfig>,
+    ) = bincode::deserialize(&payload)?;
+
+    let config = InputConfig {
+        debounce_ms,
+        buttons,
+        combo_window_ms,
+        combos,
+        layers,
+        encoder: None,
+    };
+
+    Ok(bincode::serialize(&config)?)
+}
+
+impl Schema for InputConfig {
+    const TAG: &'static str = INPUT_TAG;
+    const VERSION: u16 = 7;
+    const MAX_SIZE: usize = INPUT_FLASH_LIMIT;
+
+    fn migrations() -> &'static [Migration] {
+        &[
+            store::from_legacy,
+            from_v1,
+            from_v2,
+            from_v3,
+            from_v4,
+            from_v5,
+            from_v6,
+        ]
+    }
+}
+
+/// Reads the input configuration from flash, defaults when nothing was stored yet
+pub fn load(nvs_namespace: &EspNvs<NvsDefault>) -> InputConfig {
+    store::load(nvs_namespace).unwrap_or_default()
+}
+
+pub fn save(nvs_namespace: &mut EspNvs<NvsDefault>, config: &InputConfig) -> anyhow::Result<()> {
+    store::save(nvs_namespace, config)
+}
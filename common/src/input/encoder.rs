@@ -0,0 +1,305 @@
+use std::{sync::Arc, time::Duration};
+
+use esp_idf_hal::{
+    delay::BLOCK,
+    gpio::{AnyIOPin, AnyInputPin, Input, InterruptType, Pin, PinDriver, Pull},
+    pcnt::{
+        PcntChannel, PcntChannelConfig, PcntControlMode, PcntCountMode, PcntDriver, PinIndex, PCNT0,
+    },
+    task::{self, queue::Queue},
+};
+use esp_idf_svc::sys::{gpio_get_level, gpio_intr_enable};
+use jojo_common::{gamepad::AxisRead, message::ClientMessage};
+use log::*;
+use parking_lot::{Condvar, Mutex};
+
+use super::{
+    config::{EncoderConfig, EncoderOutput},
+    now_us,
+    quadrature::{wrap_delta, QuadratureDecoder, StepCounter},
+};
+
+// Levels not yet decoded, a fast turn raises a few edges per millisecond
+const LEVEL_QUEUE_LEN: usize = 64;
+// The pulse counter goes back to 0 at ±limit, reads are far enough apart from each other
+const PCNT_LIMIT: i16 = 10_000;
+// Glitches shorter than this many APB cycles (80 MHz) are filtered out by the pulse counter
+const PCNT_FILTER: u16 = 1023;
+const PCNT_READ_INTERVAL: Duration = Duration::from_millis(10);
+// Range of the analog axes of the joystick
+const AXIS_MAX: i32 = i16::MAX as i32;
+
+/// Channels of the encoder, counted by the pulse counter when it is given, by interrupts otherwise
+pub struct EncoderPins {
+    a: AnyIOPin,
+    b: AnyIOPin,
+    pcnt: Option<PCNT0>,
+}
+
+impl EncoderPins {
+    pub fn new(a: AnyIOPin, b: AnyIOPin, pcnt: Option<PCNT0>) -> Self {
+        EncoderPins { a, b, pcnt }
+    }
+}
+
+/// True when the firmware is built with `ROTARY_ENCODER=true`, `pcnt` or `gpio` in the `.env` file
+pub fn enabled() -> bool {
+    option_env!("ROTARY_ENCODER").is_some_and(|value| {
+        ["true", "gpio", "pcnt"].contains(&value.to_ascii_lowercase().as_str())
+    })
+}
+
+/// True unless the encoder is counted by pin interrupts, `ROTARY_ENCODER=gpio`, which loses
+/// edges when the encoder turns faster than the task drains them
+pub fn uses_pcnt() -> bool {
+    !option_env!("ROTARY_ENCODER").is_some_and(|value| value.eq_ignore_ascii_case("gpio"))
+}
+
+pub struct EncoderTask {
+    pins: EncoderPins,
+    config: EncoderConfig,
+    websocket_sender_tx: crossbeam_channel::Sender<ClientMessage>,
+    wb_status: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl EncoderTask {
+    pub fn new(
+        pins: EncoderPins,
+        config: EncoderConfig,
+        websocket_sender_tx: crossbeam_channel::Sender<ClientMessage>,
+        wb_status: Arc<(Mutex<bool>, Condvar)>,
+    ) -> Self {
+        EncoderTask {
+            pins,
+            config,
+            websocket_sender_tx,
+            wb_status,
+        }
+    }
+}
+
+enum Counter {
+    Pcnt {
+        driver: PcntDriver<'static>,
+        last: i16,
+    },
+    Gpio {
+        drivers: [PinDriver<'static, AnyIOPin, Input>; 2],
+        decoder: QuadratureDecoder,
+        levels: Arc<Queue<(bool, bool)>>,
+    },
+}
+
+impl Counter {
+    fn new(pins: EncoderPins) -> anyhow::Result<Self> {
+        match pins.pcnt {
+            Some(pcnt) => init_pcnt(pcnt, pins.a, pins.b),
+            None => init_gpio(pins.a, pins.b),
+        }
+    }
+
+    /// Waits for the encoder to turn, returns the counts turned since the last call
+    fn counts(&mut self) -> anyhow::Result<i32> {
+        match self {
+            Counter::Pcnt { driver, last } => {
+                std::thread::sleep(PCNT_READ_INTERVAL);
+
+                let current = driver.get_counter_value()?;
+                let counts = wrap_delta(*last, current, PCNT_LIMIT);
+                *last = current;
+
+                Ok(counts)
+            }
+            Counter::Gpio {
+                drivers,
+                decoder,
+                levels,
+            } => {
+                let mut counts = 0;
+                let mut timeout = BLOCK;
+
+                while let Some(((a, b), _)) = levels.recv_front(timeout) {
+                    counts += i32::from(decoder.update(a, b));
+                    timeout = 0;
+                }
+
+                // Levels are dropped while the queue is full, the ones read now catch the
+                // decoder up. A level queued meanwhile is the same state again or a neighbour
+                // and decodes fine, a whole cycle dropped is lost.
+                counts += i32::from(decoder.update(drivers[0].is_high(), drivers[1].is_high()));
+
+                Ok(counts)
+            }
+        }
+    }
+}
+
+// Both channels count on the edges of one while the other gives the direction, 4 counts per cycle
+fn init_pcnt(pcnt: PCNT0, a: AnyIOPin, b: AnyIOPin) -> anyhow::Result<Counter> {
+    let mut driver = PcntDriver::new(
+        pcnt,
+        Some(a),
+        Some(b),
+        Option::<AnyInputPin>::None,
+        Option::<AnyInputPin>::None,
+    )?;
+
+    let mut config = PcntChannelConfig::new();
+    config.lctrl_mode = PcntControlMode::Reverse;
+    config.hctrl_mode = PcntControlMode::Keep;
+    config.counter_h_lim = PCNT_LIMIT;
+    config.counter_l_lim = -PCNT_LIMIT;
+
+    config.pos_mode = PcntCountMode::Decrement;
+    config.neg_mode = PcntCountMode::Increment;
+    driver.channel_config(
+        PcntChannel::Channel0,
+        PinIndex::Pin0,
+        PinIndex::Pin1,
+        &config,
+    )?;
+
+    config.pos_mode = PcntCountMode::Increment;
+    config.neg_mode = PcntCountMode::Decrement;
+    driver.channel_config(
+        PcntChannel::Channel1,
+        PinIndex::Pin1,
+        PinIndex::Pin0,
+        &config,
+    )?;
+
+    driver.set_filter_value(PCNT_FILTER)?;
+    driver.filter_enable()?;
+
+    driver.counter_pause()?;
+    driver.counter_clear()?;
+    driver.counter_resume()?;
+
+    Ok(Counter::Pcnt { driver, last: 0 })
+}
+
+// The ISR reads both levels, by the time the task runs the encoder may have moved on. The
+// driver disables the interrupt of a pin each time it fires, the ISR enables it again right
+// away so no edge is missed until the task runs.
+fn init_gpio(a: AnyIOPin, b: AnyIOPin) -> anyhow::Result<Counter> {
+    let levels = Arc::new(Queue::new(LEVEL_QUEUE_LEN));
+    let (a_pin, b_pin) = (a.pin(), b.pin());
+
+    let mut drivers = [PinDriver::input(a)?, PinDriver::input(b)?];
+
+    for driver in drivers.iter_mut() {
+        driver.set_pull(Pull::Up)?;
+        driver.set_interrupt_type(InterruptType::AnyEdge)?;
+
+        let levels = Arc::clone(&levels);
+        let pin = driver.pin();
+
+        unsafe {
+            driver.subscribe(move || {
+                gpio_intr_enable(pin);
+
+                let read = (gpio_get_level(a_pin) != 0, gpio_get_level(b_pin) != 0);
+
+                if let Ok(true) = levels.send_back(read, 0) {
+                    task::do_yield();
+                }
+            })?;
+        }
+
+        driver.enable_interrupt()?;
+    }
+
+    let decoder = QuadratureDecoder::new(drivers[0].is_high(), drivers[1].is_high());
+
+    Ok(Counter::Gpio {
+        drivers,
+        decoder,
+        levels,
+    })
+}
+
+fn send(
+    output: &EncoderOutput,
+    steps: i32,
+    position: &mut i32,
+    websocket_sender_tx: &crossbeam_channel::Sender<ClientMessage>,
+) {
+    let message = match output {
+        EncoderOutput::Actions {
+            clockwise,
+            counter_clockwise,
+        } => {
+            let actions = if steps > 0 {
+                clockwise
+            } else {
+                counter_clockwise
+            };
+
+            if actions.is_empty() {
+                return;
+            }
+
+            let actions = actions.repeat(steps.unsigned_abs() as usize);
+            ClientMessage::ButtonActions(actions)
+        }
+        EncoderOutput::Axis { axis, scale } => {
+            *position = position
+                .saturating_add(steps.saturating_mul(*scale))
+                .clamp(0, AXIS_MAX);
+            ClientMessage::AxisRead(AxisRead::new(*axis, *position))
+        }
+    };
+
+    if let Err(err) = websocket_sender_tx.try_send(message) {
+        warn!("[encoder_task]: cannot send encoder steps {:?}", err);
+    }
+}
+
+pub fn init_task(task: EncoderTask) {
+    let EncoderTask {
+        pins,
+        config,
+        websocket_sender_tx,
+        wb_status,
+    } = task;
+
+    info!("[encoder_task]: creating");
+
+    let Some(output) = config.output.clone() else {
+        warn!("[encoder_task]: the encoder has no output, it is not read");
+        return;
+    };
+
+    let mut counter = Counter::new(pins).unwrap();
+    let mut steps = config.steps();
+    // Axes start centered
+    let mut position = AXIS_MAX / 2;
+
+    let (lock, cvar) = &*wb_status;
+
+    let mut started = lock.lock();
+
+    if !*started {
+        cvar.wait(&mut started);
+    }
+    drop(started);
+
+    info!("[encoder_task]: reading");
+
+    loop {
+        let counts = match counter.counts() {
+            Ok(counts) if config.reverse => -counts,
+            Ok(counts) => counts,
+            Err(err) => {
+                error!("[encoder_task]: cannot read the encoder {:?}", err);
+                continue;
+            }
+        };
+
+        let turned = steps.turn(counts, now_us());
+
+        if turned != 0 {
+            send(&output, turned, &mut position, &websocket_sender_tx);
+        }
+    }
+}
@@ -0,0 +1,831 @@
+use std::sync::Arc;
+
+use esp_idf_hal::{
+    delay::{Ets, TickType, BLOCK},
+    gpio::{AnyIOPin, Input, InputOutput, InterruptType, PinDriver, Pull},
+    i2c::I2cDriver,
+    task::{self, queue::Queue},
+};
+use esp_idf_svc::sys::esp_timer_get_time;
+use jojo_common::{
+    button::{ButtonAction, ButtonMode},
+    message::ClientMessage,
+};
+use log::*;
+use parking_lot::{Condvar, Mutex};
+use uuid::Uuid;
+
+pub mod board;
+pub mod config;
+pub mod encoder;
+
+pub use logic::input::{
+    combo, debounce, expander, gesture, layer, macros, matrix, mode, quadrature, validate,
+};
+
+use combo::{ComboDetector, ComboEvent};
+use config::{ButtonConfig, GestureConfig, InputConfig, InputMode};
+use debounce::Debouncer;
+use expander::{Expander, ExpanderKind, I2cBus};
+use gesture::{Gesture, GestureRecognizer};
+use layer::{LayerKey, LayerState, PressLayer};
+use macros::{MacroRunner, MacroStep};
+use matrix::{MatrixIo, MatrixScanner};
+use mode::{actions_for, Toggle, Turbo};
+
+// Edges not yet handled by the dispatcher, shared by every button
+const EDGE_QUEUE_LEN: usize = 32;
+// The matrix has no interrupts, it is scanned this often
+const MATRIX_SCAN_INTERVAL_US: u64 = 5_000;
+// Time for a column to follow the row that was just selected
+const MATRIX_SETTLE_US: u32 = 10;
+
+// Index of the edges raised by the interrupt line of the expander
+const EXPANDER_EDGE: usize = usize::MAX;
+// Read interval of an expander without interrupt line
+const EXPANDER_POLL_INTERVAL_US: u64 = 10_000;
+// With an interrupt line the expander is still read now and then, a missed edge would
+// leave the line low and the buttons stuck
+const EXPANDER_CHECK_INTERVAL_US: u64 = 100_000;
+const EXPANDER_I2C_TIMEOUT_MS: u64 = 10;
+const EXPANDER_DEFAULT_ADDRESS: u8 = 0x20;
+
+/// True when the firmware is built with `BUTTON_MATRIX=true` in the `.env` file
+pub fn matrix_enabled() -> bool {
+    option_env!("BUTTON_MATRIX").is_some_and(|value| value.eq_ignore_ascii_case("true"))
+}
+
+/// Expander set with `BUTTON_EXPANDER=mcp23017` or `pcf8574` in the `.env` file and its
+/// address, `BUTTON_EXPANDER_ADDRESS=0x21`, 0x20 when not set
+pub fn expander_config() -> Option<(ExpanderKind, u8)> {
+    let kind = ExpanderKind::from_name(option_env!("BUTTON_EXPANDER")?)?;
+    let address = option_env!("BUTTON_EXPANDER_ADDRESS")
+        .and_then(|address| {
+            let address = address.trim_start_matches("0x");
+            u8::from_str_radix(address, 16).ok()
+        })
+        .unwrap_or(EXPANDER_DEFAULT_ADDRESS);
+
+    Some((kind, address))
+}
+
+/// True when the interrupt line of the expander is wired, `BUTTON_EXPANDER_INT=true` in the
+/// `.env` file, the expander is polled otherwise
+pub fn expander_interrupt_enabled() -> bool {
+    option_env!("BUTTON_EXPANDER_INT").is_some_and(|value| value.eq_ignore_ascii_case("true"))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    button: usize,
+    at: u64,
+}
+
+/// Where a button is read from
+pub enum ButtonSource {
+    /// Pin of its own, pressed when it reads low with `Pull::Up` (or floating) and high with `Pull::Down`
+    Pin(AnyIOPin, Pull),
+    /// Key of the matrix, numbered `row * columns + column`
+    Matrix(usize),
+    /// Pin of the I2C expander
+    Expander(usize),
+}
+
+/// A button of the device
+pub struct ButtonInput {
+    id: Uuid,
+    source: ButtonSource,
+    actions: Vec<ButtonAction>,
+    mode: ButtonMode,
+}
+
+impl ButtonInput {
+    pub fn new(
+        id: Uuid,
+        source: ButtonSource,
+        actions: Vec<ButtonAction>,
+        mode: ButtonMode,
+    ) -> Self {
+        ButtonInput {
+            id,
+            source,
+            actions,
+            mode,
+        }
+    }
+}
+
+/// Pins of a key matrix, rows are pulled low one at a time and columns read with pull-ups
+pub struct MatrixPins {
+    rows: Vec<AnyIOPin>,
+    columns: Vec<AnyIOPin>,
+}
+
+impl MatrixPins {
+    pub fn new(rows: Vec<AnyIOPin>, columns: Vec<AnyIOPin>) -> Self {
+        MatrixPins { rows, columns }
+    }
+
+    pub fn keys(&self) -> usize {
+        self.rows.len() * self.columns.len()
+    }
+}
+
+struct GpioMatrix {
+    rows: Vec<PinDriver<'static, AnyIOPin, InputOutput>>,
+    columns: Vec<PinDriver<'static, AnyIOPin, Input>>,
+}
+
+impl GpioMatrix {
+    fn new(pins: MatrixPins) -> anyhow::Result<Self> {
+        let mut rows = vec![];
+        for pin in pins.rows {
+            // Open drain, unselected rows float instead of fighting a pressed key
+            let mut row = PinDriver::input_output_od(pin)?;
+            row.set_high()?;
+            rows.push(row);
+        }
+
+        let mut columns = vec![];
+        for pin in pins.columns {
+            let mut column = PinDriver::input(pin)?;
+            column.set_pull(Pull::Up)?;
+            columns.push(column);
+        }
+
+        Ok(GpioMatrix { rows, columns })
+    }
+}
+
+impl MatrixIo for GpioMatrix {
+    fn select_row(&mut self, row: usize) -> anyhow::Result<()> {
+        self.rows[row].set_low()?;
+        Ets::delay_us(MATRIX_SETTLE_US);
+        Ok(())
+    }
+
+    fn read_columns(&mut self) -> anyhow::Result<u32> {
+        Ok(self
+            .columns
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| column.is_low())
+            .fold(0, |columns, (index, _)| columns | 1 << index))
+    }
+
+    fn unselect_row(&mut self, row: usize) -> anyhow::Result<()> {
+        self.rows[row].set_high()?;
+        Ok(())
+    }
+}
+
+/// An I2C port expander, with its interrupt line when it is wired
+pub struct ExpanderBus {
+    i2c: I2cDriver<'static>,
+    expander: Expander,
+    interrupt: Option<AnyIOPin>,
+}
+
+impl ExpanderBus {
+    pub fn new(
+        i2c: I2cDriver<'static>,
+        kind: ExpanderKind,
+        address: u8,
+        interrupt: Option<AnyIOPin>,
+    ) -> Self {
+        ExpanderBus {
+            i2c,
+            expander: Expander::new(kind, address),
+            interrupt,
+        }
+    }
+
+    pub fn pins(&self) -> usize {
+        self.expander.kind().pins()
+    }
+}
+
+impl I2cBus for I2cDriver<'static> {
+    fn write(&mut self, address: u8, bytes: &[u8]) -> anyhow::Result<()> {
+        let timeout = TickType::new_millis(EXPANDER_I2C_TIMEOUT_MS).ticks();
+        I2cDriver::write(self, address, bytes, timeout)?;
+        Ok(())
+    }
+
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> anyhow::Result<()> {
+        let timeout = TickType::new_millis(EXPANDER_I2C_TIMEOUT_MS).ticks();
+        I2cDriver::read(self, address, buffer, timeout)?;
+        Ok(())
+    }
+
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> anyhow::Result<()> {
+        let timeout = TickType::new_millis(EXPANDER_I2C_TIMEOUT_MS).ticks();
+        I2cDriver::write_read(self, address, bytes, buffer, timeout)?;
+        Ok(())
+    }
+}
+
+pub struct InputTask {
+    buttons: Vec<ButtonInput>,
+    matrix: Option<MatrixPins>,
+    expander: Option<ExpanderBus>,
+    config: InputConfig,
+    websocket_sender_tx: crossbeam_channel::Sender<ClientMessage>,
+    layer_rx: crossbeam_channel::Receiver<u8>,
+    wb_status: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl InputTask {
+    pub fn new(
+        buttons: Vec<ButtonInput>,
+        matrix: Option<MatrixPins>,
+        expander: Option<ExpanderBus>,
+        config: InputConfig,
+        websocket_sender_tx: crossbeam_channel::Sender<ClientMessage>,
+        layer_rx: crossbeam_channel::Receiver<u8>,
+        wb_status: Arc<(Mutex<bool>, Condvar)>,
+    ) -> Self {
+        InputTask {
+            buttons,
+            matrix,
+            expander,
+            config,
+            websocket_sender_tx,
+            layer_rx,
+            wb_status,
+        }
+    }
+}
+
+/// What a debounced change of a button turns into
+enum Behavior {
+    /// The hold or click mode of the device
+    Device,
+    Gestures(GestureRecognizer, GestureConfig),
+    Layer(LayerKey),
+    Macro(MacroRunner, Vec<MacroStep>),
+    Toggle(Toggle),
+    Turbo(Turbo),
+}
+
+impl Behavior {
+    fn new(config: Option<&ButtonConfig>) -> Self {
+        let Some(config) = config else {
+            return Behavior::Device;
+        };
+
+        if let Some(key) = config.layer {
+            return Behavior::Layer(key);
+        }
+
+        if let Some(gestures) = &config.gestures {
+            return Behavior::Gestures(GestureRecognizer::new(gestures.timing()), gestures.clone());
+        }
+
+        if let Some(steps) = &config.macro_steps {
+            return Behavior::Macro(MacroRunner::default(), steps.clone());
+        }
+
+        match config.mode {
+            Some(InputMode::Toggle) => Behavior::Toggle(Toggle::default()),
+            Some(InputMode::Turbo { interval_ms }) => {
+                Behavior::Turbo(Turbo::new(config::repeat_interval(interval_ms)))
+            }
+            None => Behavior::Device,
+        }
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        match self {
+            Behavior::Gestures(recognizer, _) => recognizer.deadline(),
+            Behavior::Macro(runner, _) => runner.deadline(),
+            Behavior::Turbo(turbo) => turbo.deadline(),
+            Behavior::Device | Behavior::Layer(_) | Behavior::Toggle(_) => None,
+        }
+    }
+}
+
+/// A button wired to a pin of its own
+struct PinButton {
+    driver: PinDriver<'static, AnyIOPin, Input>,
+    // Buttons pulled up close to ground when pressed, pulled down ones to 3.3 V
+    active_low: bool,
+}
+
+impl PinButton {
+    fn pressed(&self) -> bool {
+        self.driver.is_low() == self.active_low
+    }
+}
+
+struct ButtonState {
+    // None for the keys of the matrix and the expander
+    pin: Option<PinButton>,
+    debouncer: Debouncer,
+    behavior: Behavior,
+    // Actions of each layer, None where the layer keeps the actions of the device
+    actions: Vec<Option<Vec<ButtonAction>>>,
+    layer: PressLayer,
+    mode: ButtonMode,
+}
+
+impl ButtonState {
+    fn actions(&self) -> &[ButtonAction] {
+        layer::resolve(&self.actions, self.layer.get())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        [self.debouncer.deadline(), self.behavior.deadline()]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+}
+
+fn now_us() -> u64 {
+    unsafe { esp_timer_get_time() as u64 }
+}
+
+fn init_button(
+    pin: AnyIOPin,
+    pull: Pull,
+    index: usize,
+    edges: Arc<Queue<Edge>>,
+) -> anyhow::Result<PinButton> {
+    let mut driver = PinDriver::input(pin)?;
+    driver.set_pull(pull)?;
+    driver.set_interrupt_type(InterruptType::AnyEdge)?;
+    subscribe_edges(&mut driver, index, edges)?;
+
+    Ok(PinButton {
+        driver,
+        // A floating pin is expected to have an external pull up, like most boards wire buttons
+        active_low: !matches!(pull, Pull::Down),
+    })
+}
+
+fn subscribe_edges(
+    driver: &mut PinDriver<'static, AnyIOPin, Input>,
+    index: usize,
+    edges: Arc<Queue<Edge>>,
+) -> anyhow::Result<()> {
+    // Runs in the ISR, the interrupt stays disabled until the dispatcher handles the edge
+    unsafe {
+        driver.subscribe(move || {
+            let edge = Edge {
+                button: index,
+                at: now_us(),
+            };
+
+            if let Ok(true) = edges.send_back(edge, 0) {
+                task::do_yield();
+            }
+        })?;
+    }
+
+    driver.enable_interrupt()?;
+
+    Ok(())
+}
+
+/// The expander pulls its open drain line low until its pins are read
+fn init_expander(
+    expander: &mut ExpanderBus,
+    edges: Arc<Queue<Edge>>,
+) -> anyhow::Result<Option<PinDriver<'static, AnyIOPin, Input>>> {
+    expander.expander.init(&mut expander.i2c)?;
+
+    let Some(pin) = expander.interrupt.take() else {
+        return Ok(None);
+    };
+
+    let mut driver = PinDriver::input(pin)?;
+    driver.set_pull(Pull::Up)?;
+    driver.set_interrupt_type(InterruptType::NegEdge)?;
+    subscribe_edges(&mut driver, EXPANDER_EDGE, edges)?;
+
+    Ok(Some(driver))
+}
+
+fn send(
+    actions: Vec<ButtonAction>,
+    websocket_sender_tx: &crossbeam_channel::Sender<ClientMessage>,
+) {
+    if actions.is_empty() {
+        return;
+    }
+
+    if let Err(err) = websocket_sender_tx.try_send(ClientMessage::ButtonActions(actions)) {
+        warn!("[input_task]: cannot send button actions {:?}", err);
+    }
+}
+
+fn send_gesture(
+    button: &ButtonState,
+    gesture: Gesture,
+    websocket_sender_tx: &crossbeam_channel::Sender<ClientMessage>,
+) {
+    let Behavior::Gestures(_, gestures) = &button.behavior else {
+        return;
+    };
+
+    let actions = match gesture {
+        Gesture::Tap(1) => button.actions(),
+        Gesture::Tap(taps) => gestures.actions(taps),
+        Gesture::LongPress => &gestures.long_press,
+        Gesture::Repeat => &gestures.repeat,
+    };
+
+    send(actions.to_vec(), websocket_sender_tx);
+}
+
+/// Handles a debounced change of a button
+fn dispatch(
+    button: &mut ButtonState,
+    pressed: bool,
+    at: u64,
+    layers: &mut LayerState,
+    websocket_sender_tx: &crossbeam_channel::Sender<ClientMessage>,
+) {
+    // A latched toggle is released with the actions of the layer it was latched on
+    let active = match &button.behavior {
+        Behavior::Toggle(toggle) => toggle.layer().unwrap_or_else(|| layers.active()),
+        _ => layers.active(),
+    };
+    let layer = button.layer.update(pressed, active);
+
+    let gesture = match &mut button.behavior {
+        Behavior::Device => {
+            send(
+                actions_for(&button.mode, button.actions(), pressed),
+                websocket_sender_tx,
+            );
+            None
+        }
+        Behavior::Gestures(recognizer, _) if pressed => recognizer.press(at),
+        Behavior::Gestures(recognizer, _) => recognizer.release(at),
+        Behavior::Layer(key) => {
+            if !layers.key(*key, pressed) {
+                warn!("[input_task]: layer key {:?} is out of range", key);
+            }
+            None
+        }
+        Behavior::Macro(runner, steps) if pressed => {
+            // A press while the macro runs is ignored, so it is never sent twice at once
+            if !runner.is_running() {
+                runner.start();
+                for batch in runner.run(steps, at) {
+                    send(batch, websocket_sender_tx);
+                }
+            }
+            None
+        }
+        Behavior::Macro(..) => None,
+        Behavior::Toggle(toggle) => {
+            // Same states the hold mode sends, only flipped on every press
+            if let Some(latched) = toggle.update(pressed, layer) {
+                send(
+                    actions_for(&ButtonMode::Hold, button.actions(), latched),
+                    websocket_sender_tx,
+                );
+            }
+            None
+        }
+        Behavior::Turbo(turbo) if pressed => {
+            turbo.press(at);
+            send(mode::click_actions(button.actions()), websocket_sender_tx);
+            None
+        }
+        Behavior::Turbo(turbo) => {
+            turbo.release();
+            None
+        }
+    };
+
+    if let Some(gesture) = gesture {
+        send_gesture(button, gesture, websocket_sender_tx);
+    }
+}
+
+/// Handles what depends on time alone, gestures waiting for their timing, macro steps
+/// and turbo shots
+fn poll(
+    button: &mut ButtonState,
+    now: u64,
+    websocket_sender_tx: &crossbeam_channel::Sender<ClientMessage>,
+) {
+    let gesture = match &mut button.behavior {
+        Behavior::Gestures(recognizer, _) => recognizer.poll(now),
+        Behavior::Macro(runner, steps) => {
+            for batch in runner.run(steps, now) {
+                send(batch, websocket_sender_tx);
+            }
+            None
+        }
+        Behavior::Turbo(turbo) => {
+            if turbo.poll(now) {
+                send(mode::click_actions(button.actions()), websocket_sender_tx);
+            }
+            None
+        }
+        Behavior::Device | Behavior::Layer(_) | Behavior::Toggle(_) => None,
+    };
+
+    if let Some(gesture) = gesture {
+        send_gesture(button, gesture, websocket_sender_tx);
+    }
+}
+
+fn handle_combo_events(
+    events: Vec<ComboEvent>,
+    buttons: &mut [ButtonState],
+    combo_actions: &[Vec<ButtonAction>],
+    layers: &mut LayerState,
+    websocket_sender_tx: &crossbeam_channel::Sender<ClientMessage>,
+) {
+    for event in events {
+        match event {
+            ComboEvent::Combo(combo) => send(combo_actions[combo].clone(), websocket_sender_tx),
+            ComboEvent::Press(button, at) => {
+                dispatch(&mut buttons[button], true, at, layers, websocket_sender_tx)
+            }
+            ComboEvent::Release(button, at) => {
+                dispatch(&mut buttons[button], false, at, layers, websocket_sender_tx)
+            }
+        }
+    }
+}
+
+/// Combos of the config as button indexes, with the actions of each one
+fn combo_indexes(config: &InputConfig, ids: &[Uuid]) -> (Vec<Vec<usize>>, Vec<Vec<ButtonAction>>) {
+    config
+        .combos
+        .iter()
+        .filter_map(|combo| {
+            let indexes: Option<Vec<usize>> = combo
+                .buttons
+                .iter()
+                .map(|id| ids.iter().position(|button| button == id))
+                .collect();
+
+            match indexes {
+                Some(mut indexes) => {
+                    indexes.sort_unstable();
+                    indexes.dedup();
+
+                    if indexes.len() < 2 {
+                        warn!("[input_task]: combo {:?} needs two buttons", combo.buttons);
+                        return None;
+                    }
+
+                    Some((indexes, combo.actions.clone()))
+                }
+                None => {
+                    warn!(
+                        "[input_task]: combo {:?} has buttons the device does not have",
+                        combo.buttons
+                    );
+                    None
+                }
+            }
+        })
+        .unzip()
+}
+
+/// Actions of the button on every layer, the ones of the device first
+fn layer_actions(
+    config: &InputConfig,
+    id: &Uuid,
+    actions: Vec<ButtonAction>,
+) -> Vec<Option<Vec<ButtonAction>>> {
+    let layers = config
+        .layers
+        .iter()
+        .map(|layer| layer.actions.get(id).cloned());
+
+    std::iter::once(Some(actions)).chain(layers).collect()
+}
+
+pub fn init_task(task: InputTask) {
+    let InputTask {
+        buttons,
+        matrix,
+        expander,
+        config,
+        websocket_sender_tx,
+        layer_rx,
+        wb_status,
+    } = task;
+
+    info!("[input_task]: creating");
+
+    let edges = Arc::new(Queue::<Edge>::new(EDGE_QUEUE_LEN));
+
+    let ids: Vec<Uuid> = buttons.iter().map(|button| button.id).collect();
+    let (combo_buttons, combo_actions) = combo_indexes(&config, &ids);
+    let mut combos = ComboDetector::new(combo_buttons, config.combo_window());
+    let mut layers = LayerState::new(config.layer_count());
+
+    let mut matrix = matrix.map(|pins| {
+        let scanner = MatrixScanner::new(pins.rows.len(), pins.columns.len());
+        (scanner, GpioMatrix::new(pins).unwrap())
+    });
+    // Button of each key of the matrix
+    let mut matrix_buttons = vec![None; matrix.as_ref().map_or(0, |(scanner, _)| scanner.keys())];
+
+    let mut expander = expander.map(|mut expander| {
+        let interrupt = init_expander(&mut expander, Arc::clone(&edges)).unwrap();
+        (expander, interrupt)
+    });
+    // Button of each pin of the expander
+    let mut expander_buttons = vec![None; expander.as_ref().map_or(0, |(bus, _)| bus.pins())];
+
+    let mut buttons: Vec<ButtonState> = buttons
+        .into_iter()
+        .enumerate()
+        .map(|(index, button)| {
+            let (pin, pressed) = match button.source {
+                ButtonSource::Pin(pin, pull) => {
+                    let pin = init_button(pin, pull, index, Arc::clone(&edges)).unwrap();
+                    let pressed = pin.pressed();
+                    (Some(pin), pressed)
+                }
+                ButtonSource::Matrix(key) => {
+                    match matrix_buttons.get_mut(key) {
+                        Some(slot) => *slot = Some(index),
+                        None => warn!("[input_task]: the matrix has no key {}", key),
+                    }
+                    (None, false)
+                }
+                ButtonSource::Expander(pin) => {
+                    match expander_buttons.get_mut(pin) {
+                        Some(slot) => *slot = Some(index),
+                        None => warn!("[input_task]: the expander has no pin {}", pin),
+                    }
+                    (None, false)
+                }
+            };
+            let debouncer = Debouncer::new(config.debounce(&button.id), pressed);
+            let behavior = Behavior::new(config.button(&button.id));
+
+            ButtonState {
+                pin,
+                debouncer,
+                behavior,
+                actions: layer_actions(&config, &button.id, button.actions),
+                layer: PressLayer::default(),
+                mode: button.mode,
+            }
+        })
+        .collect();
+
+    let (lock, cvar) = &*wb_status;
+
+    let mut started = lock.lock();
+
+    if !*started {
+        cvar.wait(&mut started);
+    }
+    drop(started);
+
+    info!("[input_task]: reading {} buttons", buttons.len());
+
+    let mut next_scan = now_us();
+    let mut next_read = now_us();
+
+    loop {
+        // Sleep until the next edge or until a held back change, a gesture, a shot or a scan is due
+        let now = now_us();
+        let timeout = buttons
+            .iter()
+            .filter_map(ButtonState::deadline)
+            .chain(combos.deadline())
+            .chain(matrix.is_some().then_some(next_scan))
+            .chain(expander.is_some().then_some(next_read))
+            .min()
+            .map(|deadline| {
+                let wait_ms = deadline.saturating_sub(now).div_ceil(1000);
+                TickType::new_millis(wait_ms).ticks().max(1)
+            })
+            .unwrap_or(BLOCK);
+
+        let edge = edges.recv_front(timeout);
+
+        // Layers switched by the server apply from the next press on
+        for layer in layer_rx.try_iter() {
+            if layers.set_base(layer.into()) {
+                info!("[input_task]: switched to layer {}", layer);
+            } else {
+                warn!("[input_task]: layer {} is out of range", layer);
+            }
+        }
+
+        if let Some((edge, _)) = edge.filter(|(edge, _)| edge.button == EXPANDER_EDGE) {
+            if let Some((_, Some(interrupt))) = expander.as_mut() {
+                if let Err(err) = interrupt.enable_interrupt() {
+                    error!("[input_task]: cannot enable interrupt {:?}", err);
+                }
+            }
+
+            next_read = next_read.min(edge.at);
+        } else if let Some((edge, _)) = edge {
+            let button = &mut buttons[edge.button];
+            let Some(pin) = button.pin.as_mut() else {
+                continue;
+            };
+
+            // Enabled before reading, so a change right after the read raises a new edge
+            if let Err(err) = pin.driver.enable_interrupt() {
+                error!("[input_task]: cannot enable interrupt {:?}", err);
+            }
+
+            let pressed = pin.pressed();
+
+            if let Some(pressed) = button.debouncer.edge(pressed, edge.at) {
+                let events = combos.change(edge.button, pressed, edge.at);
+                handle_combo_events(
+                    events,
+                    &mut buttons,
+                    &combo_actions,
+                    &mut layers,
+                    &websocket_sender_tx,
+                );
+            }
+        }
+
+        let now = now_us();
+
+        // Changes of the buttons without a pin of their own, as (button, pressed)
+        let mut changes = vec![];
+
+        if let Some((scanner, io)) = matrix.as_mut().filter(|_| now >= next_scan) {
+            next_scan = now + MATRIX_SCAN_INTERVAL_US;
+
+            match scanner.scan(io) {
+                Ok(keys) => changes.extend(
+                    keys.into_iter()
+                        .filter_map(|(key, pressed)| Some((matrix_buttons[key]?, pressed))),
+                ),
+                Err(err) => error!("[input_task]: cannot scan the matrix {:?}", err),
+            }
+        }
+
+        if let Some((bus, interrupt)) = expander.as_mut().filter(|_| now >= next_read) {
+            next_read = match interrupt {
+                Some(_) => now + EXPANDER_CHECK_INTERVAL_US,
+                None => now + EXPANDER_POLL_INTERVAL_US,
+            };
+
+            match bus.expander.poll(&mut bus.i2c) {
+                Ok(pins) => changes.extend(
+                    pins.into_iter()
+                        .filter_map(|(pin, pressed)| Some((expander_buttons[pin]?, pressed))),
+                ),
+                Err(err) => {
+                    error!("[input_task]: cannot read the expander {:?}", err);
+                    // Retried soon, the interrupt line stays low until the pins are read
+                    next_read = now + EXPANDER_POLL_INTERVAL_US;
+                }
+            }
+        }
+
+        for (index, pressed) in changes {
+            if let Some(pressed) = buttons[index].debouncer.edge(pressed, now) {
+                let events = combos.change(index, pressed, now);
+                handle_combo_events(
+                    events,
+                    &mut buttons,
+                    &combo_actions,
+                    &mut layers,
+                    &websocket_sender_tx,
+                );
+            }
+        }
+
+        for index in 0..buttons.len() {
+            if let Some(pressed) = buttons[index].debouncer.settle(now) {
+                let events = combos.change(index, pressed, now);
+                handle_combo_events(
+                    events,
+                    &mut buttons,
+                    &combo_actions,
+                    &mut layers,
+                    &websocket_sender_tx,
+                );
+            }
+
+            poll(&mut buttons[index], now, &websocket_sender_tx);
+        }
+
+        let events = combos.poll(now);
+        handle_combo_events(
+            events,
+            &mut buttons,
+            &combo_actions,
+            &mut layers,
+            &websocket_sender_tx,
+        );
+    }
+}
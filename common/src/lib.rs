@@ -1,10 +1,27 @@
+use std::time::Duration;
+
+use esp_idf_hal::sys::esp_restart;
 use esp_idf_svc::nvs::{EspNvs, EspNvsPartition, NvsDefault};
+use log::*;
 
 pub mod broadcast;
+pub mod bundle;
+pub mod calibration;
+pub mod console;
+pub mod input;
 pub mod led;
+pub mod secrets;
+pub mod settings;
+pub mod store;
 pub mod websocket;
 pub mod wifi_client;
 
+pub use logic::store::{
+    ALL_TAGS, AP_PASSWORD_TAG, CALIBRATION_TAG, DEVICE_KEY_TAG, DEVICE_TAG, INPUT_TAG, NETWORK_TAG,
+    OTP_TOKEN_TAG, SERVER_TAG,
+};
+pub use logic::{device, network};
+
 pub enum AppState {
     OTP(EspNvsPartition<NvsDefault>, EspNvs<NvsDefault>),
     CLIENT(
@@ -15,9 +32,21 @@ pub enum AppState {
 }
 
 pub const NAMESPACE: &'static str = env!("NAMESPACE");
-// TODO: this cannot cannot be more than 15 characters, find a way to type it at compile time
-pub const NETWORK_TAG: &'static str = "client_cred";
-pub const DEVICE_TAG: &'static str = "device";
+// Defaults of settings::ServerSettings, can be overridden at runtime
 pub const WEBSOCKET_PATH: &'static str = env!("WEBSOCKET_PATH");
 pub const BROADCAST_BIND_ADDRESS: &'static str = env!("BROADCAST_BIND_ADDRESS");
 pub const BROADCAST_ADDRESS: &'static str = env!("BROADCAST_ADDRESS");
+
+/// Restarts the device from another thread after `delay`, so pending responses can still be sent
+pub fn restart_after(delay: Duration) {
+    let _ = std::thread::Builder::new()
+        .stack_size(2 * 1024)
+        .spawn(move || {
+            std::thread::sleep(delay);
+            info!("[restart]: restarting device");
+            // TODO: find a more secure way to restart (the wifi driver sometimes not work on the restart)
+            unsafe {
+                esp_restart();
+            }
+        });
+}
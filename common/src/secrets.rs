@@ -0,0 +1,117 @@
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use log::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    store::{self, Migration, Schema},
+    AP_PASSWORD_TAG, OTP_TOKEN_TAG,
+};
+
+// Both are generated with a few characters, with room for longer ones set by hand
+const SECRET_MAX_SIZE: usize = 128;
+const PASSWORD_LEN: usize = 12;
+const TOKEN_LEN: usize = 16;
+// Characters easy to read from a serial log, without 0/O or 1/l/I
+const PASSWORD_CHARSET: &[u8] = b"abcdefghjkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// Password of the access point opened in OTP mode
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ApPassword(pub String);
+
+/// Token protecting the OTP server, see `otp::auth`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct OtpToken(pub String);
+
+impl From<String> for ApPassword {
+    fn from(secret: String) -> Self {
+        ApPassword(secret)
+    }
+}
+
+impl From<ApPassword> for String {
+    fn from(secret: ApPassword) -> Self {
+        secret.0
+    }
+}
+
+impl From<String> for OtpToken {
+    fn from(secret: String) -> Self {
+        OtpToken(secret)
+    }
+}
+
+impl From<OtpToken> for String {
+    fn from(secret: OtpToken) -> Self {
+        secret.0
+    }
+}
+
+impl Schema for ApPassword {
+    const TAG: &'static str = AP_PASSWORD_TAG;
+    const VERSION: u16 = 1;
+    const MAX_SIZE: usize = SECRET_MAX_SIZE;
+    const ENCRYPTED: bool = true;
+
+    fn migrations() -> &'static [Migration] {
+        &[store::from_legacy]
+    }
+}
+
+impl Schema for OtpToken {
+    const TAG: &'static str = OTP_TOKEN_TAG;
+    const VERSION: u16 = 1;
+    const MAX_SIZE: usize = SECRET_MAX_SIZE;
+    const ENCRYPTED: bool = true;
+
+    fn migrations() -> &'static [Migration] {
+        &[store::from_legacy]
+    }
+}
+
+fn generate(len: usize) -> String {
+    let mut rng = rand::thread_rng();
+
+    (0..len)
+        .map(|_| PASSWORD_CHARSET[rng.gen_range(0..PASSWORD_CHARSET.len())] as char)
+        .collect()
+}
+
+// Encrypted like the network credentials, plaintext copies of older firmwares are moved at boot
+fn load_or_create<T>(nvs_namespace: &mut EspNvs<NvsDefault>, len: usize) -> anyhow::Result<String>
+where
+    T: Schema + From<String> + Into<String>,
+{
+    if let Some(secret) = store::load::<T>(nvs_namespace) {
+        return Ok(secret.into());
+    }
+
+    let secret = generate(len);
+
+    info!("[secrets]: saving new {} in flash", T::TAG);
+    store::save(nvs_namespace, &T::from(secret.clone()))?;
+
+    Ok(secret)
+}
+
+/// Returns the access point password stored in flash, generating and storing a new one the first time
+pub fn ap_password(nvs_namespace: &mut EspNvs<NvsDefault>) -> anyhow::Result<String> {
+    load_or_create::<ApPassword>(nvs_namespace, PASSWORD_LEN)
+}
+
+/// Returns the token protecting the OTP server, generating one if there is none. It is different
+/// from the password so joining the access point is not enough to provision the device
+pub fn otp_token(nvs_namespace: &mut EspNvs<NvsDefault>) -> anyhow::Result<String> {
+    load_or_create::<OtpToken>(nvs_namespace, TOKEN_LEN)
+}
+
+pub fn stored_ap_password(nvs_namespace: &EspNvs<NvsDefault>) -> Option<String> {
+    store::load::<ApPassword>(nvs_namespace).map(String::from)
+}
+
+/// The OTP server asks for the token only when one is stored, see `otp::auth`
+pub fn stored_otp_token(nvs_namespace: &EspNvs<NvsDefault>) -> Option<String> {
+    store::load::<OtpToken>(nvs_namespace).map(String::from)
+}
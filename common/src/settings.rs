@@ -0,0 +1,203 @@
+use std::fmt;
+
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use log::*;
+use serde::{Deserialize, Deserializer, Serialize};
+
+pub use logic::settings::SettingsError;
+
+use crate::{
+    network::REDACTED,
+    store::{self, Migration, Schema},
+    BROADCAST_ADDRESS, BROADCAST_BIND_ADDRESS, SERVER_TAG, WEBSOCKET_PATH,
+};
+
+/// Connection parameters, stored in flash to override the values given at compile time
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerSettings {
+    websocket_path: String,
+    broadcast_address: String,
+    broadcast_bind_address: String,
+    auth_key: Option<String>,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings::new(
+            WEBSOCKET_PATH.to_string(),
+            BROADCAST_ADDRESS.to_string(),
+            BROADCAST_BIND_ADDRESS.to_string(),
+            None,
+        )
+    }
+}
+
+// The auth key is left out of the logs
+impl fmt::Debug for ServerSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerSettings")
+            .field("websocket_path", &self.websocket_path)
+            .field("broadcast_address", &self.broadcast_address)
+            .field("broadcast_bind_address", &self.broadcast_bind_address)
+            .field("auth_key", &self.auth_key.as_ref().map(|_| REDACTED))
+            .finish()
+    }
+}
+
+impl ServerSettings {
+    pub fn new(
+        websocket_path: String,
+        broadcast_address: String,
+        broadcast_bind_address: String,
+        auth_key: Option<String>,
+    ) -> Self {
+        ServerSettings {
+            websocket_path,
+            broadcast_address,
+            broadcast_bind_address,
+            auth_key,
+        }
+    }
+
+    pub fn websocket_path(&self) -> &str {
+        &self.websocket_path
+    }
+
+    pub fn broadcast_address(&self) -> &str {
+        &self.broadcast_address
+    }
+
+    pub fn broadcast_bind_address(&self) -> &str {
+        &self.broadcast_bind_address
+    }
+
+    pub fn auth_key(&self) -> Option<&str> {
+        self.auth_key.as_deref()
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        logic::settings::validate(
+            &self.websocket_path,
+            &self.broadcast_address,
+            &self.broadcast_bind_address,
+        )
+    }
+}
+
+/// Body of a settings update, missing fields take the compile time value except the auth key:
+/// left out it keeps the stored one, `null` or an empty string clears it
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SettingsUpdate {
+    websocket_path: String,
+    broadcast_address: String,
+    broadcast_bind_address: String,
+    #[serde(deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    auth_key: Option<Option<String>>,
+}
+
+// The auth key is left out of the logs
+impl fmt::Debug for SettingsUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SettingsUpdate")
+            .field("websocket_path", &self.websocket_path)
+            .field("broadcast_address", &self.broadcast_address)
+            .field("broadcast_bind_address", &self.broadcast_bind_address)
+            .field(
+                "auth_key",
+                &self
+                    .auth_key
+                    .as_ref()
+                    .map(|key| key.as_ref().map(|_| REDACTED)),
+            )
+            .finish()
+    }
+}
+
+impl Default for SettingsUpdate {
+    fn default() -> Self {
+        let defaults = ServerSettings::default();
+
+        SettingsUpdate {
+            websocket_path: defaults.websocket_path,
+            broadcast_address: defaults.broadcast_address,
+            broadcast_bind_address: defaults.broadcast_bind_address,
+            auth_key: None,
+        }
+    }
+}
+
+// Tells a field set to null apart from a missing one, which serde(default) leaves as None
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Option<String>>, D::Error> {
+    Option::<String>::deserialize(deserializer).map(Some)
+}
+
+impl SettingsUpdate {
+    /// Update that writes the settings back as they are, the auth key included
+    pub fn from_settings(settings: ServerSettings) -> Self {
+        SettingsUpdate {
+            websocket_path: settings.websocket_path,
+            broadcast_address: settings.broadcast_address,
+            broadcast_bind_address: settings.broadcast_bind_address,
+            auth_key: Some(settings.auth_key),
+        }
+    }
+
+    /// Leaves the auth key out, applying the update then keeps the one stored on the target
+    pub fn without_auth_key(self) -> Self {
+        SettingsUpdate {
+            auth_key: None,
+            ..self
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), SettingsError> {
+        logic::settings::validate(
+            &self.websocket_path,
+            &self.broadcast_address,
+            &self.broadcast_bind_address,
+        )
+    }
+
+    pub fn apply(self, stored: &ServerSettings) -> ServerSettings {
+        let auth_key = match self.auth_key {
+            None => stored.auth_key.clone(),
+            Some(auth_key) => auth_key.filter(|auth_key| !auth_key.is_empty()),
+        };
+
+        ServerSettings::new(
+            self.websocket_path,
+            self.broadcast_address,
+            self.broadcast_bind_address,
+            auth_key,
+        )
+    }
+}
+
+impl Schema for ServerSettings {
+    const TAG: &'static str = SERVER_TAG;
+    const VERSION: u16 = 1;
+    const MAX_SIZE: usize = 1024;
+    // The auth key is a secret
+    const ENCRYPTED: bool = true;
+
+    fn migrations() -> &'static [Migration] {
+        &[store::from_legacy]
+    }
+}
+
+/// Reads the settings from flash, falling back to the compile time values
+pub fn load(nvs_namespace: &EspNvs<NvsDefault>) -> ServerSettings {
+    store::load(nvs_namespace).unwrap_or_else(|| {
+        info!("[settings]: server settings not found, using defaults");
+        ServerSettings::default()
+    })
+}
+
+pub fn save(
+    nvs_namespace: &mut EspNvs<NvsDefault>,
+    settings: &ServerSettings,
+) -> anyhow::Result<()> {
+    store::save(nvs_namespace, settings)
+}
@@ -0,0 +1,105 @@
+use anyhow::{anyhow, bail};
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+
+pub use logic::store::blob::{chunk_key, decode_manifest, encode_manifest, CHUNK_SIZE, MAX_CHUNKS};
+
+/// Reads a single key with a buffer of its exact size
+fn read_key(nvs_namespace: &EspNvs<NvsDefault>, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(len) = nvs_namespace.blob_len(key)? else {
+        return Ok(None);
+    };
+
+    let mut buffer = vec![0; len];
+
+    Ok(nvs_namespace
+        .get_raw(key, &mut buffer)?
+        .map(|raw| raw.to_vec()))
+}
+
+fn chunk_count(nvs_namespace: &EspNvs<NvsDefault>, tag: &str) -> anyhow::Result<usize> {
+    let chunks = read_key(nvs_namespace, tag)?
+        .and_then(|raw| decode_manifest(&raw))
+        .map_or(0, |(chunks, _)| chunks);
+
+    Ok(chunks)
+}
+
+/// Reads the value stored under `tag`, joining its chunks when it was split
+pub fn read(nvs_namespace: &EspNvs<NvsDefault>, tag: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(raw) = read_key(nvs_namespace, tag)? else {
+        return Ok(None);
+    };
+
+    let Some((chunks, len)) = decode_manifest(&raw) else {
+        return Ok(Some(raw));
+    };
+
+    let mut value = Vec::with_capacity(len);
+
+    for index in 0..chunks {
+        let chunk = read_key(nvs_namespace, &chunk_key(tag, index)?)?
+            .ok_or_else(|| anyhow!("chunk {} of {} is missing", index, tag))?;
+
+        value.extend_from_slice(&chunk);
+    }
+
+    if value.len() != len {
+        bail!("{} has {} bytes, expected {}", tag, value.len(), len);
+    }
+
+    Ok(Some(value))
+}
+
+/// Writes `value` under `tag`, splitting it in chunks when it is bigger than CHUNK_SIZE.
+/// The manifest is written last so it never points to chunks of a half written value.
+pub fn write(
+    nvs_namespace: &mut EspNvs<NvsDefault>,
+    tag: &str,
+    value: &[u8],
+) -> anyhow::Result<()> {
+    let previous_chunks = chunk_count(nvs_namespace, tag)?;
+
+    let chunks = if value.len() <= CHUNK_SIZE {
+        nvs_namespace.set_raw(tag, value)?;
+        0
+    } else {
+        let chunks: Vec<&[u8]> = value.chunks(CHUNK_SIZE).collect();
+
+        if chunks.len() > MAX_CHUNKS {
+            bail!(
+                "{} takes {} bytes, it does not fit in {} chunks",
+                tag,
+                value.len(),
+                MAX_CHUNKS
+            );
+        }
+
+        for (index, chunk) in chunks.iter().enumerate() {
+            nvs_namespace.set_raw(&chunk_key(tag, index)?, chunk)?;
+        }
+
+        nvs_namespace.set_raw(tag, &encode_manifest(chunks.len(), value.len()))?;
+        chunks.len()
+    };
+
+    // Chunks left by a bigger previous value
+    for index in chunks..previous_chunks {
+        nvs_namespace.remove(&chunk_key(tag, index)?)?;
+    }
+
+    Ok(())
+}
+
+/// Removes `tag` and its chunks, works for keys written with other types too
+pub fn remove(nvs_namespace: &mut EspNvs<NvsDefault>, tag: &str) -> anyhow::Result<()> {
+    // Strings are not blobs, blob_len fails on them and they never have chunks
+    let chunks = chunk_count(nvs_namespace, tag).unwrap_or(0);
+
+    for index in 0..chunks {
+        nvs_namespace.remove(&chunk_key(tag, index)?)?;
+    }
+
+    nvs_namespace.remove(tag)?;
+
+    Ok(())
+}
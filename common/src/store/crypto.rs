@@ -0,0 +1,177 @@
+use std::fmt;
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use esp_idf_svc::{
+    nvs::{EspNvs, NvsDefault},
+    sys::{
+        esp, esp_efuse_block_t, esp_efuse_block_t_EFUSE_BLK_KEY0,
+        esp_efuse_block_t_EFUSE_BLK_KEY_MAX, esp_efuse_find_purpose,
+        esp_efuse_find_unused_key_block, esp_efuse_purpose_t_ESP_EFUSE_KEY_PURPOSE_HMAC_UP,
+        esp_efuse_write_key, esp_fill_random, esp_hmac_calculate, hmac_key_id_t,
+    },
+};
+use log::*;
+
+use crate::DEVICE_KEY_TAG;
+
+// Written before the nonce, neither the store header nor legacy bincode start like this
+const ENCRYPTED_MAGIC: [u8; 2] = [0xa5, 0x45];
+const NONCE_LEN: usize = 12;
+pub const KEY_LEN: usize = 32;
+// Signed by the HMAC peripheral to get the device key, a new version gives every device a new key
+const KEY_DERIVATION_MESSAGE: &[u8] = b"jojo store key v1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CryptoError {
+    TooShort(usize),
+    MissingKey,
+    Authentication,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CryptoError::TooShort(len) => write!(f, "encrypted value has only {} bytes", len),
+            CryptoError::MissingKey => write!(f, "value is encrypted but the device key is gone"),
+            CryptoError::Authentication => {
+                write!(f, "value does not authenticate with the device key")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+pub fn is_encrypted(raw: &[u8]) -> bool {
+    raw.starts_with(&ENCRYPTED_MAGIC)
+}
+
+/// Encrypts with ChaCha20-Poly1305, the output is magic, nonce and ciphertext with its tag
+pub fn encrypt(key: &[u8; KEY_LEN], nonce: [u8; NONCE_LEN], plaintext: &[u8]) -> Vec<u8> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .expect("chacha20poly1305 cannot fail encrypting a buffer in memory");
+
+    let mut raw = Vec::with_capacity(ENCRYPTED_MAGIC.len() + NONCE_LEN + ciphertext.len());
+    raw.extend_from_slice(&ENCRYPTED_MAGIC);
+    raw.extend_from_slice(&nonce);
+    raw.extend_from_slice(&ciphertext);
+
+    raw
+}
+
+pub fn decrypt(key: &[u8; KEY_LEN], raw: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let header_len = ENCRYPTED_MAGIC.len() + NONCE_LEN;
+    if raw.len() < header_len {
+        return Err(CryptoError::TooShort(raw.len()));
+    }
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+
+    cipher
+        .decrypt(
+            Nonce::from_slice(&raw[ENCRYPTED_MAGIC.len()..header_len]),
+            &raw[header_len..],
+        )
+        .map_err(|_| CryptoError::Authentication)
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+
+    // Backed by the hardware RNG, random as long as the radio or the bootloader entropy is on
+    unsafe { esp_fill_random(bytes.as_mut_ptr() as *mut _, N) };
+
+    bytes
+}
+
+pub fn random_nonce() -> [u8; NONCE_LEN] {
+    random()
+}
+
+fn hmac_key_block() -> Option<esp_efuse_block_t> {
+    let mut block = esp_efuse_block_t_EFUSE_BLK_KEY_MAX;
+
+    unsafe {
+        esp_efuse_find_purpose(
+            esp_efuse_purpose_t_ESP_EFUSE_KEY_PURPOSE_HMAC_UP,
+            &mut block,
+        )
+    }
+    .then_some(block)
+}
+
+/// Burns a random HMAC key in a free eFuse key block. The block is read and write protected by
+/// the write, so the key never leaves the HMAC peripheral, and this cannot be undone.
+fn burn_hmac_key() -> anyhow::Result<esp_efuse_block_t> {
+    let block = unsafe { esp_efuse_find_unused_key_block() };
+
+    if block == esp_efuse_block_t_EFUSE_BLK_KEY_MAX {
+        anyhow::bail!("no free eFuse key block for the store key");
+    }
+
+    let mut key = random::<KEY_LEN>();
+
+    warn!(
+        "[store]: burning the store key in eFuse key block {}",
+        block
+    );
+    let result = esp!(unsafe {
+        esp_efuse_write_key(
+            block,
+            esp_efuse_purpose_t_ESP_EFUSE_KEY_PURPOSE_HMAC_UP,
+            key.as_ptr() as *const _,
+            KEY_LEN,
+        )
+    });
+    key.fill(0);
+    result?;
+
+    Ok(block)
+}
+
+fn derive_key(block: esp_efuse_block_t) -> anyhow::Result<[u8; KEY_LEN]> {
+    let mut key = [0u8; KEY_LEN];
+
+    esp!(unsafe {
+        esp_hmac_calculate(
+            (block - esp_efuse_block_t_EFUSE_BLK_KEY0) as hmac_key_id_t,
+            KEY_DERIVATION_MESSAGE.as_ptr() as *const _,
+            KEY_DERIVATION_MESSAGE.len(),
+            key.as_mut_ptr(),
+        )
+    })?;
+
+    Ok(key)
+}
+
+/// Returns the key derived from the eFuse HMAC key, None if no secret was ever written
+pub fn device_key() -> anyhow::Result<Option<[u8; KEY_LEN]>> {
+    hmac_key_block().map(derive_key).transpose()
+}
+
+/// Returns the key derived from the eFuse HMAC key, burning the HMAC key the first time
+pub fn device_key_or_create() -> anyhow::Result<[u8; KEY_LEN]> {
+    let block = match hmac_key_block() {
+        Some(block) => block,
+        None => burn_hmac_key()?,
+    };
+
+    derive_key(block)
+}
+
+/// Returns the key older firmwares kept in flash next to the secrets, until they are moved to
+/// the eFuse key at boot
+pub fn legacy_key(nvs_namespace: &EspNvs<NvsDefault>) -> anyhow::Result<Option<[u8; KEY_LEN]>> {
+    let mut key = [0u8; KEY_LEN];
+
+    match nvs_namespace.get_raw(DEVICE_KEY_TAG, &mut key)? {
+        Some(raw) if raw.len() == KEY_LEN => Ok(Some(key)),
+        Some(raw) => anyhow::bail!("legacy device key has {} bytes", raw.len()),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,223 @@
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use jojo_common::network::NetworkCredentials;
+use log::*;
+use parking_lot::Mutex;
+
+use crate::{
+    secrets::{ApPassword, OtpToken},
+    settings::ServerSettings,
+    DEVICE_KEY_TAG,
+};
+
+pub mod blob;
+pub mod crypto;
+
+pub use logic::store::{
+    decode, encode, encoded_len, from_legacy, slot, split_header, CorruptionEvent, Migration,
+    Schema, StoreError, HEADER_LEN, LEGACY_VERSION,
+};
+
+use logic::store::{CorruptionLog, Selected};
+use slot::SLOTS;
+
+static CORRUPTION_EVENTS: Mutex<CorruptionLog> = Mutex::new(CorruptionLog::new());
+
+/// Returns and clears the corruption found since the last call, each copy is reported once per boot
+pub fn take_corruption_events() -> Vec<CorruptionEvent> {
+    CORRUPTION_EVENTS.lock().take()
+}
+
+/// Gets a stored value back in clear, decrypting it when needed
+fn open(nvs_namespace: &EspNvs<NvsDefault>, raw: &[u8]) -> Result<Vec<u8>, String> {
+    if !crypto::is_encrypted(raw) {
+        return Ok(raw.to_vec());
+    }
+
+    let key = crypto::device_key().map_err(|err| err.to_string())?;
+    // Values written before the eFuse key stay under the flash one until they are moved at boot
+    let legacy = crypto::legacy_key(nvs_namespace).map_err(|err| err.to_string())?;
+
+    if key.is_none() && legacy.is_none() {
+        return Err(crypto::CryptoError::MissingKey.to_string());
+    }
+
+    [key, legacy]
+        .into_iter()
+        .flatten()
+        .find_map(|key| crypto::decrypt(&key, raw).ok())
+        .ok_or_else(|| crypto::CryptoError::Authentication.to_string())
+}
+
+fn read_slot<T: Schema>(
+    nvs_namespace: &EspNvs<NvsDefault>,
+    slot: char,
+) -> Option<Result<(u32, T), String>> {
+    let raw = match blob::read(nvs_namespace, &slot::slot_key(T::TAG, slot)) {
+        Ok(raw) => raw?,
+        Err(err) => return Some(Err(err.to_string())),
+    };
+
+    Some(logic::store::decode_slot(&raw, |value| {
+        open(nvs_namespace, value)
+    }))
+}
+
+/// Reads a value from flash, taking the newest good copy of the two slots.
+/// Unreadable data is logged, queued for the server and reported as missing so boot can go on.
+pub fn load<T: Schema>(nvs_namespace: &EspNvs<NvsDefault>) -> Option<T> {
+    let slots = SLOTS.map(|slot| read_slot::<T>(nvs_namespace, slot));
+
+    // Written before the A/B slots, it moves to them on the next save
+    let legacy = || match blob::read(nvs_namespace, T::TAG) {
+        Ok(raw) => raw.map(|raw| {
+            open(nvs_namespace, &raw)
+                .and_then(|plaintext| decode(&plaintext).map_err(|err| err.to_string()))
+        }),
+        Err(err) => Some(Err(err.to_string())),
+    };
+
+    let Selected { value, corrupted } = logic::store::select(T::TAG, slots, legacy);
+
+    for event in corrupted {
+        error!(
+            "[store]: {} slot {:?} is corrupted ({}), recovered: {}",
+            event.tag, event.slot, event.detail, event.recovered
+        );
+
+        CORRUPTION_EVENTS.lock().push(event);
+    }
+
+    value
+}
+
+/// Removes a tag written by the store with its slots, or any other key in the namespace
+pub fn remove(nvs_namespace: &mut EspNvs<NvsDefault>, tag: &str) -> anyhow::Result<()> {
+    for slot in SLOTS {
+        blob::remove(nvs_namespace, &slot::slot_key(tag, slot))?;
+    }
+
+    blob::remove(nvs_namespace, tag)
+}
+
+/// Writes the value to the slot that does not hold the newest good copy, so a power loss while
+/// writing leaves the previous value readable
+pub fn save<T: Schema>(nvs_namespace: &mut EspNvs<NvsDefault>, value: &T) -> anyhow::Result<()> {
+    let encoded = encode(value)?;
+    let encoded = if T::ENCRYPTED {
+        let key = crypto::device_key_or_create()?;
+        crypto::encrypt(&key, crypto::random_nonce(), &encoded)
+    } else {
+        encoded
+    };
+
+    let sequences = SLOTS.map(|slot| {
+        blob::read(nvs_namespace, &slot::slot_key(T::TAG, slot))
+            .ok()
+            .flatten()
+            .and_then(|raw| slot::decode_record(&raw).ok().map(|record| record.sequence))
+    });
+    let (slot, sequence) = slot::next_slot(sequences);
+
+    blob::write(
+        nvs_namespace,
+        &slot::slot_key(T::TAG, slot),
+        &slot::encode_record(sequence, &encoded),
+    )?;
+
+    // The copy from before the A/B slots is not read anymore
+    if nvs_namespace.contains(T::TAG)? {
+        blob::remove(nvs_namespace, T::TAG)?;
+    }
+
+    Ok(())
+}
+
+/// Rewrites both slots of a value holding secrets when a copy is still in plaintext,
+/// values written by older firmwares are encrypted this way on the first boot
+pub fn encrypt_plaintext<T: Schema>(nvs_namespace: &mut EspNvs<NvsDefault>) -> anyhow::Result<()> {
+    if !T::ENCRYPTED {
+        return Ok(());
+    }
+
+    let plaintext_slot = SLOTS.iter().any(|slot| {
+        blob::read(nvs_namespace, &slot::slot_key(T::TAG, *slot))
+            .ok()
+            .flatten()
+            .is_some_and(|raw| {
+                slot::decode_record(&raw).is_ok_and(|record| !crypto::is_encrypted(record.value))
+            })
+    });
+    let legacy = nvs_namespace.contains(T::TAG)?;
+
+    if !plaintext_slot && !legacy {
+        return Ok(());
+    }
+
+    let Some(value) = load::<T>(nvs_namespace) else {
+        return Ok(());
+    };
+
+    info!("[store]: encrypting {}", T::TAG);
+
+    // Once per slot, so the copy in the other one does not stay readable
+    save(nvs_namespace, &value)?;
+    save(nvs_namespace, &value)?;
+
+    Ok(())
+}
+
+/// Moves a secret that older firmwares wrote as an NVS string under its tag to the encrypted slots
+pub fn encrypt_plaintext_str<T>(nvs_namespace: &mut EspNvs<NvsDefault>) -> anyhow::Result<()>
+where
+    T: Schema + From<String>,
+{
+    let buffer: &mut [u8] = &mut vec![0; T::MAX_SIZE];
+
+    let Some(secret) = nvs_namespace.get_str(T::TAG, buffer)? else {
+        return Ok(());
+    };
+    let value = T::from(secret.to_string());
+
+    info!("[store]: encrypting {}", T::TAG);
+
+    save(nvs_namespace, &value)?;
+    // Removed only once the encrypted copy is written, a power loss before only repeats this
+    nvs_namespace.remove(T::TAG)?;
+
+    Ok(())
+}
+
+/// Rewrites both slots of a value, which encrypts it under the current device key
+fn reencrypt<T: Schema>(nvs_namespace: &mut EspNvs<NvsDefault>) -> anyhow::Result<()> {
+    let Some(value) = load::<T>(nvs_namespace) else {
+        return Ok(());
+    };
+
+    info!("[store]: moving {} to the eFuse key", T::TAG);
+
+    save(nvs_namespace, &value)?;
+    save(nvs_namespace, &value)?;
+
+    Ok(())
+}
+
+/// Encrypts every value holding secrets that is still stored in plaintext, and moves the ones
+/// encrypted under the key older firmwares kept in flash to the eFuse key
+pub fn encrypt_secrets(nvs_namespace: &mut EspNvs<NvsDefault>) -> anyhow::Result<()> {
+    encrypt_plaintext::<NetworkCredentials>(nvs_namespace)?;
+    encrypt_plaintext::<ServerSettings>(nvs_namespace)?;
+    encrypt_plaintext_str::<ApPassword>(nvs_namespace)?;
+    encrypt_plaintext_str::<OtpToken>(nvs_namespace)?;
+
+    if crypto::legacy_key(nvs_namespace)?.is_some() {
+        reencrypt::<NetworkCredentials>(nvs_namespace)?;
+        reencrypt::<ServerSettings>(nvs_namespace)?;
+        reencrypt::<ApPassword>(nvs_namespace)?;
+        reencrypt::<OtpToken>(nvs_namespace)?;
+
+        // Removed only once every secret is rewritten, a power loss before only repeats this
+        nvs_namespace.remove(DEVICE_KEY_TAG)?;
+    }
+
+    Ok(())
+}
@@ -3,38 +3,103 @@ use esp_idf_svc::nvs::{EspNvs, NvsDefault};
 use jojo_common::{device::Device, message::ServerMessage};
 use log::*;
 use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
 use std::{
     net::{SocketAddr, TcpStream},
     sync::Arc,
     time::Duration,
 };
 
-use tungstenite::{client::client_with_config, protocol::WebSocketConfig, Message, WebSocket};
+use tungstenite::{
+    client::{client_with_config, IntoClientRequest},
+    http::HeaderValue,
+    protocol::WebSocketConfig,
+    Message, WebSocket,
+};
+
+use crate::{
+    bundle::{self, ConfigBundle},
+    device::DEVICE_FLASH_LIMIT,
+    input, store, NETWORK_TAG,
+};
+
+// Big enough for a config bundle in JSON with the biggest device allowed in flash
+const MAX_MESSAGE_SIZE: usize = 2 * DEVICE_FLASH_LIMIT + 4 * 1024;
+// Delay before restarting so the reply can reach the server
+const RESTART_DELAY: Duration = Duration::from_millis(500);
+
+/// Commands sent by the server as JSON text frames, e.g. `{"command": "export_config"}`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum ServerCommand {
+    /// Networks and the auth key are only exported with `"include_secrets": true`
+    ExportConfig {
+        #[serde(default)]
+        include_secrets: bool,
+    },
+    ImportConfig {
+        bundle: ConfigBundle,
+    },
+    /// Switches the layer the buttons use while no layer key is held, 0 is the device one
+    SetLayer {
+        layer: u8,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum CommandReply {
+    Config {
+        bundle: ConfigBundle,
+    },
+    ImportResult {
+        imported: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Layer {
+        layer: u8,
+    },
+    Error {
+        error: String,
+    },
+}
 
-use crate::{DEVICE_TAG, NETWORK_TAG};
+/// Events the device reports on its own, also as JSON text frames
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum ClientEvent {
+    ConfigCorrupted(store::CorruptionEvent),
+}
 
 pub struct WebsocketTask<'a> {
     path: &'a str,
+    auth_key: Option<&'a str>,
     discovery_rx: crossbeam_channel::Receiver<SocketAddr>,
     websocket_sender_rx: crossbeam_channel::Receiver<jojo_common::message::ClientMessage>,
+    layer_tx: crossbeam_channel::Sender<u8>,
     status: Arc<(Mutex<bool>, Condvar)>,
     device: Device,
-    nvs_namespace: EspNvs<NvsDefault>,
+    nvs_namespace: Arc<Mutex<EspNvs<NvsDefault>>>,
 }
 
 impl<'a> WebsocketTask<'a> {
     pub fn new(
         path: &'a str,
+        auth_key: Option<&'a str>,
         discovery_rx: crossbeam_channel::Receiver<SocketAddr>,
         websocket_sender_rx: crossbeam_channel::Receiver<jojo_common::message::ClientMessage>,
+        layer_tx: crossbeam_channel::Sender<u8>,
         status: Arc<(Mutex<bool>, Condvar)>,
         device: Device,
-        nvs_namespace: EspNvs<NvsDefault>,
+        nvs_namespace: Arc<Mutex<EspNvs<NvsDefault>>>,
     ) -> Self {
         WebsocketTask {
             path,
+            auth_key,
             discovery_rx,
             websocket_sender_rx,
+            layer_tx,
             status,
             device,
             nvs_namespace,
@@ -91,11 +156,13 @@ enum WebsocketStates {
 pub fn init_task(task: WebsocketTask) {
     let WebsocketTask {
         path,
+        auth_key,
         discovery_rx,
         websocket_sender_rx,
+        layer_tx,
         status,
         device,
-        mut nvs_namespace,
+        nvs_namespace,
     } = task;
 
     let mut main_state = WebsocketState::default();
@@ -125,14 +192,25 @@ pub fn init_task(task: WebsocketTask) {
                     .set_read_timeout(Some(Duration::from_millis(25)))
                     .unwrap();
 
+                let mut request = format!("ws://{}/{}/{}", server_address, path, device.id())
+                    .into_client_request()
+                    .unwrap();
+
+                if let Some(auth_key) = auth_key {
+                    request.headers_mut().insert(
+                        "Authorization",
+                        HeaderValue::from_str(&format!("Bearer {}", auth_key)).unwrap(),
+                    );
+                }
+
                 let (socket, _broadcast_discovery) = client_with_config(
-                    &format!("ws://{}/{}/{}", server_address, path, device.id()),
+                    request,
                     stream,
                     Some(WebSocketConfig {
                         write_buffer_size: 1024,
-                        max_message_size: Some(2048),
-                        max_write_buffer_size: 2048,
-                        max_frame_size: Some(2048),
+                        max_message_size: Some(MAX_MESSAGE_SIZE),
+                        max_write_buffer_size: MAX_MESSAGE_SIZE,
+                        max_frame_size: Some(MAX_MESSAGE_SIZE),
                         accept_unmasked_frames: false,
                         ..WebSocketConfig::default()
                     }),
@@ -157,6 +235,18 @@ pub fn init_task(task: WebsocketTask) {
                         .lock()
                         .send(Message::Binary(bincode::serialize(&message).unwrap()))
                         .unwrap();
+
+                    // Corruption found while loading the config at boot
+                    for event in store::take_corruption_events() {
+                        warn!("[websocket_task]:Reporting corrupted {}", event.tag);
+
+                        let event = ClientEvent::ConfigCorrupted(event);
+
+                        socket_tx
+                            .lock()
+                            .send(Message::Text(serde_json::to_string(&event).unwrap()))
+                            .unwrap();
+                    }
                 }
 
                 info!("[websocket_task]: init read task");
@@ -169,7 +259,13 @@ pub fn init_task(task: WebsocketTask) {
                         if let Some(mut socket) = socket_rx.try_lock() {
                             if let Ok(message) = socket.read() {
                                 // info!("[websocket_task]:Rx: {:?}", message);
-                                message_handler(message, &device, &mut nvs_namespace);
+                                if let Some(reply) =
+                                    message_handler(message, &device, &nvs_namespace, &layer_tx)
+                                {
+                                    if let Err(err) = socket.send(reply) {
+                                        error!("[websocket_task]: cannot send reply {:?}", err);
+                                    }
+                                }
                                 socket.flush().unwrap();
                             }
                         }
@@ -210,69 +306,165 @@ pub fn init_task(task: WebsocketTask) {
     }
 }
 
+/// Handles a message from the server, returning the reply to send back if any
 pub fn message_handler(
     wb_message: Message,
     device: &Device,
-    nvs_namespace: &mut EspNvs<NvsDefault>,
-) {
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+    layer_tx: &crossbeam_channel::Sender<u8>,
+) -> Option<Message> {
     match wb_message {
         Message::Binary(server_message) => {
-            let Ok(message) = bincode::deserialize::<ServerMessage>(&server_message) else {
-                error!(
+            match bincode::deserialize::<ServerMessage>(&server_message) {
+                Ok(message) => server_message_handler(message, device, nvs_namespace),
+                Err(_) => error!(
                     "[message_handler]: {:?} : cannot deserialize message",
                     server_message
-                );
-                return;
-            };
+                ),
+            }
 
-            match message {
-                ServerMessage::UpdateDevice(_, button_actions) => {
-                    // TODO: we need to update flash with this new actions_map. We need to check if all buttons has actions to not break things.
-                    // TODO: we can create a channel to communicate with a task owner of flash to update it or maybe pass the nvs handler to this task.
-                    let mut new_device = device.clone();
-                    let mut new_actions_map = device.actions_map().clone();
-
-                    new_actions_map.extend(button_actions);
-                    new_device.set_actions_map(new_actions_map);
-
-                    info!("[message_handler::UpdateDevice]: updating flash with new device");
-
-                    nvs_namespace
-                        .set_raw(
-                            DEVICE_TAG,
-                            &bincode::serialize(&new_device)
-                                .expect("[message_handler::UpdateDevice]: cannot serialize device"),
-                        )
-                        .expect(
-                            "[message_handler::UpdateDevice]: a problem occur while writing flash",
-                        );
-
-                    // TODO: we need to restart because we need to create all button tasks again, maybe we can find a way to avoid it
-                    // maybe we can use a channel to trigger task creation, the problem is how to erase previous tasks from memory
-                    info!("[message_handler::UpdateDevice]: restarting device");
-
-                    // TODO: find a more secure way to restart (the wifi driver sometimes not work on the restart)
-                    unsafe {
-                        esp_restart();
+            None
+        }
+        Message::Text(text) => {
+            let reply = match serde_json::from_str::<ServerCommand>(&text) {
+                Ok(command) => command_handler(command, nvs_namespace, layer_tx),
+                Err(err) => {
+                    // The text may carry a bundle with passwords, only its size is logged
+                    error!(
+                        "[message_handler]: cannot parse command of {} bytes",
+                        text.len()
+                    );
+                    CommandReply::Error {
+                        error: err.to_string(),
                     }
                 }
-                ServerMessage::RestartDevice(_) => {
-                    // TODO: restart device
-                    info!("[message_handler::RestartDevice]: restarting device");
-                    unsafe {
-                        esp_restart();
+            };
+
+            match serde_json::to_string(&reply) {
+                Ok(reply) => Some(Message::Text(reply)),
+                Err(err) => {
+                    error!("[message_handler]: cannot serialize reply {:?}", err);
+                    None
+                }
+            }
+        }
+        _ => {
+            info!("[message_handler]: {:?}", wb_message);
+            None
+        }
+    }
+}
+
+fn server_message_handler(
+    message: ServerMessage,
+    device: &Device,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+) {
+    match message {
+        ServerMessage::UpdateDevice(_, button_actions) => {
+            // TODO: we can create a channel to communicate with a task owner of flash to update it or maybe pass the nvs handler to this task.
+            let mut new_device = device.clone();
+            let mut new_actions_map = device.actions_map().clone();
+
+            new_actions_map.extend(button_actions);
+            new_device.set_actions_map(new_actions_map);
+
+            if let Err(err) = crate::device::validate(&new_device) {
+                error!("[message_handler::UpdateDevice]: invalid device {}", err);
+                return;
+            }
+
+            info!("[message_handler::UpdateDevice]: updating flash with new device");
+
+            store::save(&mut nvs_namespace.lock(), &new_device)
+                .expect("[message_handler::UpdateDevice]: a problem occur while writing flash");
+
+            // TODO: we need to restart because we need to create all button tasks again, maybe we can find a way to avoid it
+            // maybe we can use a channel to trigger task creation, the problem is how to erase previous tasks from memory
+            info!("[message_handler::UpdateDevice]: restarting device");
+
+            // TODO: find a more secure way to restart (the wifi driver sometimes not work on the restart)
+            unsafe {
+                esp_restart();
+            }
+        }
+        ServerMessage::RestartDevice(_) => {
+            // TODO: restart device
+            info!("[message_handler::RestartDevice]: restarting device");
+            unsafe {
+                esp_restart();
+            }
+        }
+        ServerMessage::ClearCredentials(_) => {
+            info!("[message_handler::ClearCredentials]: erasing network credentials");
+            store::remove(&mut nvs_namespace.lock(), NETWORK_TAG).unwrap();
+            info!("[message_handler::ClearCredentials]: restarting device");
+            unsafe {
+                esp_restart();
+            }
+        }
+    }
+}
+
+fn command_handler(
+    command: ServerCommand,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+    layer_tx: &crossbeam_channel::Sender<u8>,
+) -> CommandReply {
+    match command {
+        ServerCommand::ExportConfig { include_secrets } => {
+            info!(
+                "[command_handler::ExportConfig]: exporting configuration, secrets included: {}",
+                include_secrets
+            );
+
+            match bundle::export(&nvs_namespace.lock(), include_secrets) {
+                Ok(bundle) => CommandReply::Config { bundle },
+                Err(err) => CommandReply::Error {
+                    error: err.to_string(),
+                },
+            }
+        }
+        ServerCommand::SetLayer { layer } => {
+            let layer_count = input::config::load(&nvs_namespace.lock()).layer_count();
+
+            if usize::from(layer) >= layer_count {
+                return CommandReply::Error {
+                    error: format!("layer {} does not exist, there are {}", layer, layer_count),
+                };
+            }
+
+            info!("[command_handler::SetLayer]: switching to layer {}", layer);
+
+            match layer_tx.try_send(layer) {
+                Ok(()) => CommandReply::Layer { layer },
+                Err(err) => CommandReply::Error {
+                    error: err.to_string(),
+                },
+            }
+        }
+        ServerCommand::ImportConfig { bundle } => {
+            info!("[command_handler::ImportConfig]: importing configuration");
+
+            match bundle::import(&mut nvs_namespace.lock(), &bundle) {
+                Ok(()) => {
+                    // Every task reads its configuration at boot
+                    crate::restart_after(RESTART_DELAY);
+
+                    CommandReply::ImportResult {
+                        imported: true,
+                        error: None,
                     }
                 }
-                ServerMessage::ClearCredentials(_) => {
-                    info!("[message_handler::ClearCredentials]: erasing network credentials");
-                    nvs_namespace.remove(NETWORK_TAG).unwrap();
-                    info!("[message_handler::ClearCredentials]: restarting device");
-                    unsafe {
-                        esp_restart();
+                Err(err) => {
+                    error!("[command_handler::ImportConfig]: {}", err);
+
+                    CommandReply::ImportResult {
+                        imported: false,
+                        error: Some(err.to_string()),
                     }
                 }
             }
         }
-        _ => info!("[message_handler]: {:?}", wb_message),
     }
 }
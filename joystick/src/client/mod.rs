@@ -1,11 +1,10 @@
 use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 
 use anyhow::Result;
-use common::{broadcast, led, websocket, wifi_client, DEVICE_TAG, WEBSOCKET_PATH};
+use common::{broadcast, console, input, led, settings, store, websocket, wifi_client};
 use crossbeam_channel::unbounded;
 use esp_idf_hal::{
     adc::{self, AdcChannelDriver, AdcDriver},
-    gpio::{AnyIOPin, Pull},
     prelude::Peripherals,
 };
 use esp_idf_svc::{
@@ -24,8 +23,6 @@ use rgb::RGB8;
 
 pub mod peripherals;
 
-use crate::client::peripherals::button::ButtonTask;
-
 pub fn main(
     nvs_default: EspNvsPartition<NvsDefault>,
     network_credentials: jojo_common::network::NetworkCredentials,
@@ -50,18 +47,34 @@ pub fn main(
     let wb_status = Arc::new((Mutex::new(false), Condvar::new()));
     let wb_status_cloned = Arc::clone(&wb_status);
     let wb_status_axis = Arc::clone(&wb_status);
+    let wb_status_encoder = Arc::clone(&wb_status);
 
     // TODO: review this channel, maybe replace it with two broadcast channels, for websocket and discovery
     let (discovery_tx, discovery_rx) = unbounded::<SocketAddr>();
 
     // Channels to send websocket messages
     let (wb_sender_tx, wb_sender_rx) = unbounded::<jojo_common::message::ClientMessage>();
+
+    // Layers switched by the server
+    let (layer_tx, layer_rx) = unbounded::<u8>();
+    let encoder_wb_sender_tx = wb_sender_tx.clone();
     let axis_wb_sender_tx = wb_sender_tx.clone();
 
     info!("[client_task]: getting device");
 
     let device = get_device(&mut nvs_namespace)?;
 
+    let server_settings = settings::load(&nvs_namespace);
+
+    info!("[client_task]: server settings {:?}", server_settings);
+
+    let input_config = input::config::load(&nvs_namespace);
+
+    let broadcast_settings = server_settings.clone();
+
+    let nvs_namespace = Arc::new(Mutex::new(nvs_namespace));
+    let nvs_namespace_console = Arc::clone(&nvs_namespace);
+
     info!("[client_task]: creating tasks");
 
     let _wifi_thread = std::thread::Builder::new()
@@ -82,20 +95,32 @@ pub fn main(
     let _broadcast_discovery = std::thread::Builder::new()
         .name("broadcast_discovery".into())
         .stack_size(6 * 1024)
-        .spawn(|| {
-            broadcast::init_task(broadcast::BroadcastTask::new(wifi_status_bd, discovery_tx))
+        .spawn(move || {
+            broadcast::init_task(broadcast::BroadcastTask::new(
+                wifi_status_bd,
+                discovery_tx,
+                broadcast_settings.broadcast_bind_address().to_string(),
+                broadcast_settings.broadcast_address().to_string(),
+            ))
         })?;
 
+    let _console_thread = std::thread::Builder::new()
+        .name("console_thread".into())
+        .stack_size(6 * 1024)
+        .spawn(|| console::init_task(console::ConsoleTask::new("CLIENT", nvs_namespace_console)))?;
+
     let cloned_device = device.clone();
 
     let _websocket_thread = std::thread::Builder::new()
         .name("websocket_thread".into())
         .stack_size(24 * 1024)
-        .spawn(|| {
+        .spawn(move || {
             websocket::init_task(websocket::WebsocketTask::new(
-                WEBSOCKET_PATH,
+                server_settings.websocket_path().trim_matches('/'),
+                server_settings.auth_key(),
                 discovery_rx,
                 wb_sender_rx,
+                layer_tx,
                 wb_status,
                 cloned_device,
                 nvs_namespace,
@@ -142,49 +167,59 @@ pub fn main(
             ))
         })?;
 
-    // TODO: find a more idiomatic way of doing this, maybe a builder pattern may help
-    let mut gpios: Vec<(AnyIOPin, Pull)> = vec![
-        (peripherals.pins.gpio0.into(), Pull::Down),
-        (peripherals.pins.gpio6.into(), Pull::Up),
-    ];
-
-    let mut actions_map = device.actions_map().clone();
-    let buttons = device.buttons().clone();
-
-    info!("[buttons]: {:?}", buttons);
-    info!("[actions]: {:?}", actions_map);
-
-    for button in buttons {
-        info!("[button]: {:?}, gpio_len: {:?}", button, gpios.len());
-
-        let (pin, pull) = gpios.pop().expect("cannot unwrap gpio");
-        let action = actions_map.remove(&button.id()).expect(
-            format!(
-                "cannot unwrap button.id: {:?}, actions: {:?}",
-                actions_map,
-                button.id()
-            )
-            .as_str(),
-        );
-        let gpio_wb_sender_tx = wb_sender_tx.clone();
-        let wb_status_gpio = Arc::clone(&wb_status_cloned);
-        let button_task: ButtonTask = ButtonTask::new(
-            pin,
-            gpio_wb_sender_tx,
-            wb_status_gpio,
-            action,
-            button.mode().clone(),
-            pull,
-        );
+    let mut input_pins = input::board::InputPins::new(input::board::InputPeripherals {
+        gpio0: peripherals.pins.gpio0,
+        gpio6: peripherals.pins.gpio6,
+        gpio7: peripherals.pins.gpio7,
+        gpio8: peripherals.pins.gpio8,
+        gpio9: peripherals.pins.gpio9,
+        gpio10: peripherals.pins.gpio10,
+        gpio11: peripherals.pins.gpio11,
+        gpio12: peripherals.pins.gpio12,
+        gpio1: peripherals.pins.gpio1,
+        gpio2: peripherals.pins.gpio2,
+        gpio13: peripherals.pins.gpio13,
+        i2c0: peripherals.i2c0,
+        gpio16: peripherals.pins.gpio16,
+        gpio17: peripherals.pins.gpio17,
+        pcnt0: peripherals.pcnt0,
+    })?;
+    let inputs = input_pins.button_inputs(&device);
+
+    if let Some(pins) = input_pins.encoder.take() {
+        let encoder_config = input_config.encoder.clone().unwrap_or_default();
+
+        info!("[client_task]: creating encoder task");
+
+        let _encoder_thread = std::thread::Builder::new()
+            .name("encoder_thread".into())
+            .stack_size(4 * 1024)
+            .spawn(move || {
+                input::encoder::init_task(input::encoder::EncoderTask::new(
+                    pins,
+                    encoder_config,
+                    encoder_wb_sender_tx,
+                    wb_status_encoder,
+                ))
+            })?;
+    }
 
-        info!("[client_task]: creating gpio task");
+    info!("[client_task]: creating input task");
 
-        let _gpio = std::thread::Builder::new()
-            .name("gpio_thread".into())
-            .stack_size(6 * 1024)
-            .spawn(move || peripherals::button::init_task(button_task))
-            .unwrap();
-    }
+    let _input_thread = std::thread::Builder::new()
+        .name("input_thread".into())
+        .stack_size(6 * 1024)
+        .spawn(move || {
+            input::init_task(input::InputTask::new(
+                inputs,
+                input_pins.matrix,
+                input_pins.expander,
+                input_config,
+                wb_sender_tx,
+                layer_rx,
+                wb_status_cloned,
+            ))
+        })?;
 
     // let _ = std::thread::Builder::new().stack_size(4 * 1024).spawn(|| {
     //     let time = Instant::now();
@@ -198,16 +233,14 @@ pub fn main(
 }
 
 fn get_device(nvs_namespace: &mut EspNvs<NvsDefault>) -> Result<Device> {
-    let buffer: &mut [u8] = &mut [0; 500];
-
     // nvs_namespace.remove(NETWORK_TAG).unwrap();
     // nvs_namespace.remove(DEVICE_TAG).unwrap();
 
     // TODO: replace this with something more elegant
     let main_device;
 
-    if let Some(device) = nvs_namespace.get_raw(DEVICE_TAG, buffer)? {
-        main_device = bincode::deserialize(device)?;
+    if let Some(device) = store::load::<Device>(nvs_namespace) {
+        main_device = device;
         info!("[main_task]: device found in flash {:?}", main_device);
     } else {
         // TODO: create device and store it
@@ -241,9 +274,7 @@ fn get_device(nvs_namespace: &mut EspNvs<NvsDefault>) -> Result<Device> {
 
         info!("[main_task]: saving device in flash {:?}", device);
 
-        nvs_namespace
-            .set_raw(DEVICE_TAG, &bincode::serialize(&device)?)
-            .unwrap();
+        store::save(nvs_namespace, &device).unwrap();
 
         main_device = device;
     };
@@ -1,4 +1,4 @@
-use common::{AppState, NAMESPACE, NETWORK_TAG};
+use common::{network, store, AppState, NAMESPACE};
 use esp_idf_svc::{
     log::EspLogger,
     nvs::{EspDefaultNvsPartition, EspNvs, EspNvsPartition, NvsDefault},
@@ -23,21 +23,27 @@ fn main() -> anyhow::Result<()> {
         Err(e) => panic!("Could't get namespace {:?}", e),
     };
 
-    let buffer: &mut [u8] = &mut [0; 200];
-
-    let state: AppState = match nvs_namespace.get_raw(NETWORK_TAG, buffer)? {
-        Some(network_credentials) => {
-            let decode: jojo_common::network::NetworkCredentials =
-                bincode::deserialize(network_credentials)?;
-            info!("[main_task]: Network credentials found: {:?}", decode);
+    // Secrets written by older firmwares are still in plaintext
+    if let Err(err) = store::encrypt_secrets(&mut nvs_namespace) {
+        error!("[main_task]: cannot encrypt secrets {:?}", err);
+    }
 
-            AppState::CLIENT(nvs_default, decode, nvs_namespace)
-        }
-        None => {
-            info!("[main_task]: Network credentials not found");
-            AppState::OTP(nvs_default, nvs_namespace)
-        }
-    };
+    // Unreadable credentials are logged by the store and handled as missing, so we end up in OTP mode
+    let state: AppState =
+        match store::load::<jojo_common::network::NetworkCredentials>(&nvs_namespace) {
+            Some(decode) => {
+                info!(
+                    "[main_task]: Network credentials found: {:?}",
+                    network::Redacted(&decode)
+                );
+
+                AppState::CLIENT(nvs_default, decode, nvs_namespace)
+            }
+            None => {
+                info!("[main_task]: Network credentials not found");
+                AppState::OTP(nvs_default, nvs_namespace)
+            }
+        };
 
     // TODO: think a way to reset client credentials (go from CLIENT -> OTP)
     match state {
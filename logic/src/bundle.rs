@@ -0,0 +1,52 @@
+use std::fmt;
+
+use crate::{
+    device::{DeviceError, DEVICE_FLASH_LIMIT},
+    input::validate::InputError,
+    network::CredentialsError,
+    settings::SettingsError,
+};
+
+/// Bumped whenever a field changes meaning, older bundles are rejected instead of guessed
+pub const BUNDLE_VERSION: u32 = 1;
+// Biggest calibration accepted in flash, a few reads per analog input
+pub const CALIBRATION_FLASH_LIMIT: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BundleError {
+    UnsupportedVersion(u32),
+    Credentials(CredentialsError),
+    Device(DeviceError),
+    DeviceTooLarge(usize),
+    Settings(SettingsError),
+    CalibrationTooLarge(usize),
+    Input(InputError),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::UnsupportedVersion(version) => write!(
+                f,
+                "bundle version {} is not supported, expected {}",
+                version, BUNDLE_VERSION
+            ),
+            BundleError::Credentials(err) => write!(f, "networks: {}", err),
+            BundleError::Device(err) => write!(f, "device: {}", err),
+            BundleError::DeviceTooLarge(size) => write!(
+                f,
+                "device: takes {} bytes, the limit is {}",
+                size, DEVICE_FLASH_LIMIT
+            ),
+            BundleError::Settings(err) => write!(f, "server_settings: {}", err),
+            BundleError::CalibrationTooLarge(size) => write!(
+                f,
+                "calibration: takes {} bytes, the limit is {}",
+                size, CALIBRATION_FLASH_LIMIT
+            ),
+            BundleError::Input(err) => write!(f, "input: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {}
@@ -0,0 +1,237 @@
+use std::fmt;
+
+use crate::network::{self, CredentialsError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Status,
+    WifiShow,
+    WifiSet { ssid: String, password: String },
+    WifiClear,
+    DeviceShow,
+    AuthOn,
+    AuthOff,
+    CalibrationClear,
+    SecretsShow,
+    NvsErase,
+    Reboot,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    UnclosedQuote,
+    Unknown(String),
+    Usage(&'static str),
+    Credentials(CredentialsError),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty command"),
+            ParseError::UnclosedQuote => write!(f, "missing closing quote"),
+            ParseError::Unknown(command) => {
+                write!(f, "unknown command {:?}, type help", command)
+            }
+            ParseError::Usage(usage) => write!(f, "usage: {}", usage),
+            ParseError::Credentials(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+pub const HELP: &str = "\
+help                        show this message
+status                      show mode, uptime, memory and stored configuration
+wifi show                   show the stored network
+wifi set <ssid> [password]  store network credentials, quote values with spaces
+wifi clear                  erase the stored network, next boot goes to OTP mode
+device show                 print the stored device as JSON
+auth on                     protect the OTP server with a token and print it
+auth off                    leave the OTP server open
+secrets show                print the access point password and the OTP server token
+calibration clear           measure the analog inputs again on the next boot
+nvs erase --yes             erase every stored value
+reboot                      restart the device";
+
+/// Splits a line by whitespace, double quotes group words and backslash escapes the next character
+pub fn tokenize(line: &str) -> Result<Vec<String>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut current: Option<String> = None;
+    let mut quoted = false;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    current.get_or_insert_with(String::new).push(escaped);
+                }
+            }
+            '"' => {
+                quoted = !quoted;
+                current.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => {
+                if let Some(token) = current.take() {
+                    tokens.push(token);
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quoted {
+        return Err(ParseError::UnclosedQuote);
+    }
+
+    if let Some(token) = current {
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+pub fn parse(line: &str) -> Result<Command, ParseError> {
+    let tokens = tokenize(line)?;
+    let words: Vec<&str> = tokens.iter().map(String::as_str).collect();
+
+    match words.as_slice() {
+        [] => Err(ParseError::Empty),
+        ["help"] => Ok(Command::Help),
+        ["status"] => Ok(Command::Status),
+        ["wifi", "show"] => Ok(Command::WifiShow),
+        ["wifi", "clear"] => Ok(Command::WifiClear),
+        ["wifi", "set", ssid] => wifi_set(ssid, ""),
+        ["wifi", "set", ssid, password] => wifi_set(ssid, password),
+        ["wifi", ..] => Err(ParseError::Usage(
+            "wifi show | wifi set <ssid> [password] | wifi clear",
+        )),
+        ["device", "show"] => Ok(Command::DeviceShow),
+        ["device", ..] => Err(ParseError::Usage("device show")),
+        ["auth", "on"] => Ok(Command::AuthOn),
+        ["auth", "off"] => Ok(Command::AuthOff),
+        ["auth", ..] => Err(ParseError::Usage("auth on | auth off")),
+        ["secrets", "show"] => Ok(Command::SecretsShow),
+        ["secrets", ..] => Err(ParseError::Usage("secrets show")),
+        ["calibration", "clear"] => Ok(Command::CalibrationClear),
+        ["calibration", ..] => Err(ParseError::Usage("calibration clear")),
+        // Nothing survives an erase, the flag confirms it was not typed by accident
+        ["nvs", "erase", "--yes"] => Ok(Command::NvsErase),
+        ["nvs", ..] => Err(ParseError::Usage(
+            "nvs erase --yes, every stored value is lost",
+        )),
+        ["reboot"] => Ok(Command::Reboot),
+        [command, ..] => Err(ParseError::Unknown(command.to_string())),
+    }
+}
+
+fn wifi_set(ssid: &str, password: &str) -> Result<Command, ParseError> {
+    network::validate_credentials(ssid, password).map_err(ParseError::Credentials)?;
+
+    Ok(Command::WifiSet {
+        ssid: ssid.to_string(),
+        password: password.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_group_words() {
+        assert_eq!(
+            parse(r#"wifi set "My network" password"#),
+            Ok(Command::WifiSet {
+                ssid: "My network".to_string(),
+                password: "password".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn backslash_escapes_quotes_and_spaces() {
+        assert_eq!(
+            tokenize(r#"wifi set My\ \"net\" "pass word""#),
+            Ok(vec![
+                "wifi".to_string(),
+                "set".to_string(),
+                r#"My "net""#.to_string(),
+                "pass word".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn empty_quotes_are_a_token() {
+        assert_eq!(
+            tokenize(r#"wifi set "" """#),
+            Ok(vec![
+                "wifi".to_string(),
+                "set".to_string(),
+                String::new(),
+                String::new(),
+            ])
+        );
+    }
+
+    #[test]
+    fn open_network_has_no_password() {
+        assert_eq!(
+            parse("wifi set cafe"),
+            Ok(Command::WifiSet {
+                ssid: "cafe".to_string(),
+                password: String::new(),
+            })
+        );
+    }
+
+    #[test]
+    fn unclosed_quote_is_an_error() {
+        assert_eq!(
+            parse(r#"wifi set "My network"#),
+            Err(ParseError::UnclosedQuote)
+        );
+    }
+
+    #[test]
+    fn unknown_command() {
+        assert_eq!(
+            parse("format c:"),
+            Err(ParseError::Unknown("format".to_string()))
+        );
+    }
+
+    #[test]
+    fn missing_arguments_print_the_usage() {
+        assert!(matches!(parse("wifi set"), Err(ParseError::Usage(_))));
+        assert!(matches!(parse("wifi"), Err(ParseError::Usage(_))));
+        assert!(matches!(parse("device"), Err(ParseError::Usage(_))));
+        assert!(matches!(parse("auth"), Err(ParseError::Usage(_))));
+        assert!(matches!(parse("secrets"), Err(ParseError::Usage(_))));
+        assert!(matches!(parse("calibration"), Err(ParseError::Usage(_))));
+        assert!(matches!(parse("nvs"), Err(ParseError::Usage(_))));
+    }
+
+    #[test]
+    fn erase_needs_confirmation() {
+        assert!(matches!(parse("nvs erase"), Err(ParseError::Usage(_))));
+        assert!(matches!(parse("nvs erase yes"), Err(ParseError::Usage(_))));
+        assert_eq!(parse("nvs erase --yes"), Ok(Command::NvsErase));
+    }
+
+    #[test]
+    fn blank_line_is_empty() {
+        assert_eq!(parse("   "), Err(ParseError::Empty));
+    }
+
+    #[test]
+    fn credentials_are_validated() {
+        assert_eq!(
+            parse("wifi set cafe short"),
+            Err(ParseError::Credentials(CredentialsError::PasswordTooShort))
+        );
+    }
+}
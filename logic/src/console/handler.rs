@@ -0,0 +1,243 @@
+use super::command::{Command, HELP};
+
+/// Everything the console needs from the device, implemented over flash and esp-idf on the target
+pub trait Backend {
+    fn mode(&self) -> &'static str;
+    fn uptime_secs(&self) -> u64;
+    fn free_heap(&self) -> u32;
+    fn credentials_ssid(&self) -> anyhow::Result<Option<String>>;
+    fn set_credentials(&mut self, ssid: &str, password: &str) -> anyhow::Result<()>;
+    fn clear_credentials(&mut self) -> anyhow::Result<()>;
+    fn device_json(&self) -> anyhow::Result<Option<String>>;
+    fn ap_password(&self) -> anyhow::Result<Option<String>>;
+    fn otp_token(&self) -> anyhow::Result<Option<String>>;
+    /// Returns the token, the stored one or a new one when there is none
+    fn enable_auth(&mut self) -> anyhow::Result<String>;
+    fn disable_auth(&mut self) -> anyhow::Result<()>;
+    fn clear_calibration(&mut self) -> anyhow::Result<()>;
+    fn erase(&mut self) -> anyhow::Result<()>;
+    fn reboot(&mut self);
+}
+
+/// Runs a command and returns the text to print back
+pub fn execute(command: Command, backend: &mut impl Backend) -> String {
+    let result = match command {
+        Command::Help => Ok(HELP.to_string()),
+        Command::Status => status(backend),
+        Command::WifiShow => backend.credentials_ssid().map(|ssid| match ssid {
+            Some(ssid) => format!("ssid: {}", ssid),
+            None => "no network stored".to_string(),
+        }),
+        Command::WifiSet { ssid, password } => backend
+            .set_credentials(&ssid, &password)
+            .map(|_| format!("network {} stored, reboot to connect", ssid)),
+        Command::WifiClear => backend
+            .clear_credentials()
+            .map(|_| "network erased, reboot to enter OTP mode".to_string()),
+        Command::DeviceShow => backend
+            .device_json()
+            .map(|device| device.unwrap_or_else(|| "no device stored".to_string())),
+        Command::AuthOn => backend.enable_auth().map(|token| {
+            format!(
+                "OTP server token: {}\nsend it as the password of the browser prompt, reboot to apply",
+                token
+            )
+        }),
+        Command::AuthOff => backend
+            .disable_auth()
+            .map(|_| "OTP server auth off, reboot to apply".to_string()),
+        Command::SecretsShow => secrets(backend),
+        Command::CalibrationClear => backend
+            .clear_calibration()
+            .map(|_| "calibration erased, reboot with the inputs at rest".to_string()),
+        Command::NvsErase => backend
+            .erase()
+            .map(|_| "flash erased, reboot to start from scratch".to_string()),
+        Command::Reboot => {
+            backend.reboot();
+            Ok("rebooting".to_string())
+        }
+    };
+
+    result.unwrap_or_else(|err| format!("error: {}", err))
+}
+
+fn status(backend: &impl Backend) -> anyhow::Result<String> {
+    let ssid = backend.credentials_ssid()?;
+    let device = backend.device_json()?;
+    let auth = backend.otp_token()?.is_some();
+
+    Ok(format!(
+        "mode: {}\nuptime: {}s\nfree heap: {} bytes\nnetwork: {}\ndevice: {}\notp auth: {}",
+        backend.mode(),
+        backend.uptime_secs(),
+        backend.free_heap(),
+        ssid.as_deref().unwrap_or("none"),
+        if device.is_some() { "stored" } else { "none" },
+        if auth { "on" } else { "off" },
+    ))
+}
+
+fn secrets(backend: &impl Backend) -> anyhow::Result<String> {
+    let password = backend.ap_password()?;
+    let token = backend.otp_token()?;
+
+    Ok(format!(
+        "access point password: {}\nOTP server token: {}",
+        password
+            .as_deref()
+            .unwrap_or("none, generated on the next OTP boot"),
+        token.as_deref().unwrap_or("none, auth is off"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeBackend {
+        ssid: Option<String>,
+        token: Option<String>,
+        rebooted: bool,
+        fail: bool,
+    }
+
+    impl Backend for FakeBackend {
+        fn mode(&self) -> &'static str {
+            "otp"
+        }
+
+        fn uptime_secs(&self) -> u64 {
+            42
+        }
+
+        fn free_heap(&self) -> u32 {
+            1024
+        }
+
+        fn credentials_ssid(&self) -> anyhow::Result<Option<String>> {
+            Ok(self.ssid.clone())
+        }
+
+        fn set_credentials(&mut self, ssid: &str, _password: &str) -> anyhow::Result<()> {
+            if self.fail {
+                anyhow::bail!("flash is full");
+            }
+            self.ssid = Some(ssid.to_string());
+            Ok(())
+        }
+
+        fn clear_credentials(&mut self) -> anyhow::Result<()> {
+            self.ssid = None;
+            Ok(())
+        }
+
+        fn device_json(&self) -> anyhow::Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn ap_password(&self) -> anyhow::Result<Option<String>> {
+            Ok(Some("password".to_string()))
+        }
+
+        fn otp_token(&self) -> anyhow::Result<Option<String>> {
+            Ok(self.token.clone())
+        }
+
+        fn enable_auth(&mut self) -> anyhow::Result<String> {
+            Ok(self
+                .token
+                .get_or_insert_with(|| "token".to_string())
+                .clone())
+        }
+
+        fn disable_auth(&mut self) -> anyhow::Result<()> {
+            self.token = None;
+            Ok(())
+        }
+
+        fn clear_calibration(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn erase(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn reboot(&mut self) {
+            self.rebooted = true;
+        }
+    }
+
+    #[test]
+    fn wifi_set_stores_the_network() {
+        let mut backend = FakeBackend::default();
+        let command = Command::WifiSet {
+            ssid: "home".to_string(),
+            password: "password".to_string(),
+        };
+
+        assert_eq!(
+            execute(command, &mut backend),
+            "network home stored, reboot to connect"
+        );
+        assert_eq!(execute(Command::WifiShow, &mut backend), "ssid: home");
+    }
+
+    #[test]
+    fn status_reports_the_backend() {
+        let mut backend = FakeBackend::default();
+
+        assert_eq!(
+            execute(Command::Status, &mut backend),
+            "mode: otp\nuptime: 42s\nfree heap: 1024 bytes\nnetwork: none\ndevice: none\notp auth: off"
+        );
+    }
+
+    #[test]
+    fn auth_keeps_the_token_until_turned_off() {
+        let mut backend = FakeBackend::default();
+
+        assert!(execute(Command::AuthOn, &mut backend).starts_with("OTP server token: token\n"));
+        assert!(execute(Command::Status, &mut backend).ends_with("otp auth: on"));
+
+        execute(Command::AuthOff, &mut backend);
+        assert!(execute(Command::Status, &mut backend).ends_with("otp auth: off"));
+    }
+
+    #[test]
+    fn backend_errors_are_printed() {
+        let mut backend = FakeBackend {
+            fail: true,
+            ..Default::default()
+        };
+        let command = Command::WifiSet {
+            ssid: "home".to_string(),
+            password: String::new(),
+        };
+
+        assert_eq!(execute(command, &mut backend), "error: flash is full");
+    }
+
+    #[test]
+    fn secrets_are_printed_on_demand() {
+        let mut backend = FakeBackend::default();
+
+        assert_eq!(
+            execute(Command::SecretsShow, &mut backend),
+            "access point password: password\nOTP server token: none, auth is off"
+        );
+
+        execute(Command::AuthOn, &mut backend);
+        assert!(execute(Command::SecretsShow, &mut backend).ends_with("OTP server token: token"));
+    }
+
+    #[test]
+    fn reboot_is_requested() {
+        let mut backend = FakeBackend::default();
+
+        execute(Command::Reboot, &mut backend);
+        assert!(backend.rebooted);
+    }
+}
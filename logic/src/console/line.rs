@@ -0,0 +1,129 @@
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+/// Assembles lines from the raw bytes typed in a serial terminal
+#[derive(Debug)]
+pub struct LineBuffer {
+    buffer: Vec<u8>,
+    max_len: usize,
+    overflow: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineEvent {
+    Line(String),
+    // The line was longer than max_len and has been discarded
+    Overflow,
+}
+
+impl LineBuffer {
+    pub fn new(max_len: usize) -> Self {
+        LineBuffer {
+            buffer: Vec::with_capacity(max_len),
+            max_len,
+            overflow: false,
+        }
+    }
+
+    pub fn push(&mut self, byte: u8) -> Option<LineEvent> {
+        match byte {
+            b'\r' | b'\n' => {
+                if std::mem::take(&mut self.overflow) {
+                    self.buffer.clear();
+                    return Some(LineEvent::Overflow);
+                }
+                if self.buffer.is_empty() {
+                    return None;
+                }
+                let line = String::from_utf8_lossy(&self.buffer).trim().to_string();
+                self.buffer.clear();
+                Some(LineEvent::Line(line))
+            }
+            BACKSPACE | DELETE => {
+                self.buffer.pop();
+                None
+            }
+            _ if self.overflow => None,
+            _ if self.buffer.len() >= self.max_len => {
+                self.overflow = true;
+                None
+            }
+            _ => {
+                self.buffer.push(byte);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_in(line_buffer: &mut LineBuffer, bytes: &[u8]) -> Vec<LineEvent> {
+        bytes
+            .iter()
+            .filter_map(|byte| line_buffer.push(*byte))
+            .collect()
+    }
+
+    #[test]
+    fn lines_end_on_cr_or_lf() {
+        let mut line_buffer = LineBuffer::new(16);
+
+        assert_eq!(
+            type_in(&mut line_buffer, b"status\r\nhelp\n"),
+            vec![
+                LineEvent::Line("status".to_string()),
+                LineEvent::Line("help".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_lines_are_skipped() {
+        let mut line_buffer = LineBuffer::new(16);
+
+        assert_eq!(type_in(&mut line_buffer, b"\r\n\n"), vec![]);
+    }
+
+    #[test]
+    fn backspace_and_delete_remove_the_last_byte() {
+        let mut line_buffer = LineBuffer::new(16);
+
+        assert_eq!(
+            type_in(&mut line_buffer, b"statux\x08s\x7f\x7fus\n"),
+            vec![LineEvent::Line("status".to_string())]
+        );
+    }
+
+    #[test]
+    fn backspace_on_an_empty_line_does_nothing() {
+        let mut line_buffer = LineBuffer::new(16);
+
+        assert_eq!(
+            type_in(&mut line_buffer, b"\x08\x08help\n"),
+            vec![LineEvent::Line("help".to_string())]
+        );
+    }
+
+    #[test]
+    fn surrounding_spaces_are_trimmed() {
+        let mut line_buffer = LineBuffer::new(16);
+
+        assert_eq!(
+            type_in(&mut line_buffer, b"  reboot \n"),
+            vec![LineEvent::Line("reboot".to_string())]
+        );
+    }
+
+    #[test]
+    fn long_lines_overflow_and_the_next_one_is_read() {
+        let mut line_buffer = LineBuffer::new(4);
+
+        assert_eq!(
+            type_in(&mut line_buffer, b"status\nhelp\n"),
+            vec![LineEvent::Overflow, LineEvent::Line("help".to_string())]
+        );
+    }
+}
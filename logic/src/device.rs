@@ -0,0 +1,48 @@
+use std::{collections::HashSet, fmt};
+
+use jojo_common::device::Device;
+use uuid::Uuid;
+
+// Biggest device accepted in flash, keeps a runaway config from filling the partition
+pub const DEVICE_FLASH_LIMIT: usize = 16 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceError {
+    DuplicatedButton(Uuid),
+    MissingActions(Uuid),
+    UnknownButton(Uuid),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::DuplicatedButton(id) => write!(f, "button {} is duplicated", id),
+            DeviceError::MissingActions(id) => write!(f, "button {} has no actions", id),
+            DeviceError::UnknownButton(id) => {
+                write!(f, "actions reference an unknown button {}", id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+/// Checks a device can be used to create the button tasks: unique buttons, each one with its actions
+pub fn validate(device: &Device) -> Result<(), DeviceError> {
+    let mut ids: HashSet<Uuid> = HashSet::new();
+
+    for button in device.buttons() {
+        if !ids.insert(button.id()) {
+            return Err(DeviceError::DuplicatedButton(button.id()));
+        }
+        if !device.actions_map().contains_key(&button.id()) {
+            return Err(DeviceError::MissingActions(button.id()));
+        }
+    }
+
+    if let Some(id) = device.actions_map().keys().find(|id| !ids.contains(id)) {
+        return Err(DeviceError::UnknownButton(*id));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use std::net::Ipv4Addr;
+
+const HEADER_LEN: usize = 12;
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+const ANSWER_TTL: u32 = 60;
+
+/// Builds a response for `query` that resolves every A question to `ip`.
+/// Returns None for packets that are not standard queries.
+pub fn answer(query: &[u8], ip: Ipv4Addr) -> Option<Vec<u8>> {
+    if query.len() < HEADER_LEN {
+        return None;
+    }
+
+    let flags = u16::from_be_bytes([query[2], query[3]]);
+    let is_response = flags & 0x8000 != 0;
+    let opcode = (flags >> 11) & 0x0f;
+    let question_count = u16::from_be_bytes([query[4], query[5]]);
+
+    if is_response || opcode != 0 || question_count == 0 {
+        return None;
+    }
+
+    // Walk the first question name, compressed names are not expected in queries
+    let mut offset = HEADER_LEN;
+    loop {
+        let label_len = *query.get(offset)? as usize;
+        offset += 1;
+        if label_len == 0 {
+            break;
+        }
+        if label_len & 0xc0 != 0 {
+            return None;
+        }
+        offset += label_len;
+    }
+
+    let question_end = offset + 4;
+    let question = query.get(HEADER_LEN..question_end)?;
+    let qtype = u16::from_be_bytes([query[offset], query[offset + 1]]);
+    let qclass = u16::from_be_bytes([query[offset + 2], query[offset + 3]]);
+    let answer_count: u16 = if qtype == TYPE_A && qclass == CLASS_IN {
+        1
+    } else {
+        0
+    };
+
+    let mut response = Vec::with_capacity(question_end + 16);
+
+    // Header: same id, QR + AA set, keep opcode and RD from the query
+    response.extend_from_slice(&query[0..2]);
+    response.extend_from_slice(&(0x8400 | (flags & 0x7900)).to_be_bytes());
+    response.extend_from_slice(&1u16.to_be_bytes());
+    response.extend_from_slice(&answer_count.to_be_bytes());
+    response.extend_from_slice(&0u16.to_be_bytes());
+    response.extend_from_slice(&0u16.to_be_bytes());
+
+    response.extend_from_slice(question);
+
+    if answer_count == 1 {
+        // Pointer to the name in the question section
+        response.extend_from_slice(&0xc00cu16.to_be_bytes());
+        response.extend_from_slice(&TYPE_A.to_be_bytes());
+        response.extend_from_slice(&CLASS_IN.to_be_bytes());
+        response.extend_from_slice(&ANSWER_TTL.to_be_bytes());
+        response.extend_from_slice(&4u16.to_be_bytes());
+        response.extend_from_slice(&ip.octets());
+    }
+
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AP_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 71, 1);
+    const TYPE_AAAA: u16 = 28;
+
+    fn question(name: &str, qtype: u16) -> Vec<u8> {
+        let mut question = vec![];
+        for label in name.split('.') {
+            question.push(label.len() as u8);
+            question.extend_from_slice(label.as_bytes());
+        }
+        question.push(0);
+        question.extend_from_slice(&qtype.to_be_bytes());
+        question.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+        question
+    }
+
+    // Standard query with recursion desired
+    fn query(questions: &[&[u8]]) -> Vec<u8> {
+        let mut query = vec![0x12, 0x34, 0x01, 0x00];
+        query.extend_from_slice(&(questions.len() as u16).to_be_bytes());
+        query.extend_from_slice(&[0; 6]);
+        for question in questions {
+            query.extend_from_slice(question);
+        }
+
+        query
+    }
+
+    fn count(response: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes([response[offset], response[offset + 1]])
+    }
+
+    #[test]
+    fn a_query_resolves_to_the_access_point() {
+        let question = question("connectivitycheck.gstatic.com", TYPE_A);
+        let response = answer(&query(&[&question]), AP_IP).unwrap();
+
+        assert_eq!(&response[0..2], &[0x12, 0x34]);
+        // Response, authoritative, recursion desired kept
+        assert_eq!(count(&response, 2), 0x8500);
+        assert_eq!(count(&response, 4), 1);
+        assert_eq!(count(&response, 6), 1);
+        assert_eq!(
+            &response[HEADER_LEN..HEADER_LEN + question.len()],
+            &question[..]
+        );
+
+        let record = &response[HEADER_LEN + question.len()..];
+        assert_eq!(&record[0..2], &[0xc0, 0x0c]);
+        assert_eq!(count(record, 2), TYPE_A);
+        assert_eq!(count(record, 4), CLASS_IN);
+        assert_eq!(&record[6..10], &ANSWER_TTL.to_be_bytes());
+        assert_eq!(count(record, 10), 4);
+        assert_eq!(&record[12..], &AP_IP.octets());
+    }
+
+    #[test]
+    fn other_types_get_an_empty_answer() {
+        let question = question("captive.apple.com", TYPE_AAAA);
+        let response = answer(&query(&[&question]), AP_IP).unwrap();
+
+        assert_eq!(count(&response, 4), 1);
+        assert_eq!(count(&response, 6), 0);
+        assert_eq!(response.len(), HEADER_LEN + question.len());
+    }
+
+    #[test]
+    fn truncated_packets_are_dropped() {
+        let full = query(&[&question("example.com", TYPE_A)]);
+
+        for len in 0..full.len() {
+            assert_eq!(answer(&full[..len], AP_IP), None, "{} bytes", len);
+        }
+    }
+
+    #[test]
+    fn responses_and_empty_queries_are_dropped() {
+        let mut response = query(&[&question("example.com", TYPE_A)]);
+        response[2] |= 0x80;
+        assert_eq!(answer(&response, AP_IP), None);
+
+        assert_eq!(answer(&query(&[]), AP_IP), None);
+    }
+
+    #[test]
+    fn only_the_first_question_is_answered() {
+        let first = question("example.com", TYPE_A);
+        let second = question("example.org", TYPE_A);
+        let response = answer(&query(&[&first, &second]), AP_IP).unwrap();
+
+        assert_eq!(count(&response, 4), 1);
+        assert_eq!(count(&response, 6), 1);
+        assert_eq!(&response[HEADER_LEN..HEADER_LEN + first.len()], &first[..]);
+        assert_eq!(response.len(), HEADER_LEN + first.len() + 16);
+    }
+}
@@ -0,0 +1,323 @@
+use std::fmt;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    bundle::BundleError, device::DeviceError, network::CredentialsError, settings::SettingsError,
+};
+
+pub const JSON: &str = "application/json";
+
+/// Error returned by the HTTP handlers, rendered as JSON by the ErrorMiddleware
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    status: u16,
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<&'static str>,
+}
+
+impl ApiError {
+    pub fn new(status: u16, code: &'static str, message: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            code,
+            message: message.into(),
+            field: None,
+        }
+    }
+
+    pub fn with_field(mut self, field: &'static str) -> Self {
+        self.field = Some(field);
+        self
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        ApiError::new(400, "bad_request", message)
+    }
+
+    pub fn invalid_json(message: impl Into<String>) -> Self {
+        ApiError::new(400, "invalid_json", message)
+    }
+
+    pub fn invalid_field(field: &'static str, message: impl Into<String>) -> Self {
+        ApiError::new(400, "invalid_field", message).with_field(field)
+    }
+
+    pub fn unauthorized() -> Self {
+        ApiError::new(401, "unauthorized", "missing or wrong device token")
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        ApiError::new(404, "not_found", message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        ApiError::new(409, "conflict", message)
+    }
+
+    pub fn payload_too_large(limit: usize) -> Self {
+        ApiError::new(
+            413,
+            "payload_too_large",
+            format!("body cannot be bigger than {} bytes", limit),
+        )
+    }
+
+    pub fn unsupported_media_type(expected: &str) -> Self {
+        ApiError::new(
+            415,
+            "unsupported_media_type",
+            format!("Content-Type must be {}", expected),
+        )
+    }
+
+    /// The cause is logged by the caller, it can carry details the client must not see
+    pub fn internal() -> Self {
+        ApiError::new(500, "internal", "internal error")
+    }
+
+    pub fn timeout(message: impl Into<String>) -> Self {
+        ApiError::new(504, "timeout", message)
+    }
+
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    pub fn field(&self) -> Option<&'static str> {
+        self.field
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: {}", self.status, self.code, self.message)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
+        ApiError::invalid_json(err.to_string())
+    }
+}
+
+impl From<CredentialsError> for ApiError {
+    fn from(err: CredentialsError) -> Self {
+        ApiError::invalid_field(err.field(), err.to_string())
+    }
+}
+
+impl From<DeviceError> for ApiError {
+    fn from(err: DeviceError) -> Self {
+        let field = match err {
+            DeviceError::DuplicatedButton(_) => "buttons",
+            DeviceError::MissingActions(_) | DeviceError::UnknownButton(_) => "actions_map",
+        };
+
+        ApiError::invalid_field(field, err.to_string())
+    }
+}
+
+impl From<BundleError> for ApiError {
+    fn from(err: BundleError) -> Self {
+        let field = match err {
+            BundleError::UnsupportedVersion(_) => "version",
+            BundleError::Credentials(_) => "networks",
+            BundleError::Device(_) | BundleError::DeviceTooLarge(_) => "device",
+            BundleError::Settings(_) => "server_settings",
+            BundleError::CalibrationTooLarge(_) => "calibration",
+            BundleError::Input(_) => "input",
+        };
+
+        ApiError::invalid_field(field, err.to_string())
+    }
+}
+
+impl From<SettingsError> for ApiError {
+    fn from(err: SettingsError) -> Self {
+        let field = match err {
+            SettingsError::EmptyWebsocketPath => "websocket_path",
+            SettingsError::InvalidAddress(field, _) => field,
+        };
+
+        ApiError::invalid_field(field, err.to_string())
+    }
+}
+
+/// Rejects bodies that are not sent as JSON, parameters like charset are allowed
+pub fn require_json(content_type: Option<&str>) -> Result<(), ApiError> {
+    let is_json = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case(JSON));
+
+    if !is_json {
+        return Err(ApiError::unsupported_media_type(JSON));
+    }
+
+    Ok(())
+}
+
+/// Reads a whole body through `read` until it returns 0, refusing it past `limit` bytes
+pub fn read_body(
+    content_len: Option<u64>,
+    limit: usize,
+    mut read: impl FnMut(&mut [u8]) -> anyhow::Result<usize>,
+) -> anyhow::Result<Vec<u8>> {
+    // Refuse early when the client announces a body we are not going to accept
+    if content_len.is_some_and(|content_len| content_len > limit as u64) {
+        Err(ApiError::payload_too_large(limit))?;
+    }
+
+    let mut body = Vec::new();
+    let mut chunk = [0u8; 256];
+
+    loop {
+        let bytes_read = read(&mut chunk)?;
+
+        if bytes_read == 0 {
+            break;
+        }
+        if body.len() + bytes_read > limit {
+            Err(ApiError::payload_too_large(limit))?;
+        }
+
+        body.extend_from_slice(&chunk[0..bytes_read]);
+    }
+
+    Ok(body)
+}
+
+pub fn parse_json<T: DeserializeOwned>(body: &[u8]) -> Result<T, ApiError> {
+    Ok(serde_json::from_slice(body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn body(err: &ApiError) -> serde_json::Value {
+        serde_json::to_value(err).unwrap()
+    }
+
+    // Serves `body` in chunks of at most `chunk` bytes, like a connection would
+    fn reader(body: &[u8], chunk: usize) -> impl FnMut(&mut [u8]) -> anyhow::Result<usize> + '_ {
+        let mut sent = 0;
+
+        move |buffer| {
+            let len = chunk.min(buffer.len()).min(body.len() - sent);
+            buffer[..len].copy_from_slice(&body[sent..sent + len]);
+            sent += len;
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn json_with_parameters_is_accepted() {
+        assert_eq!(require_json(Some("application/json")), Ok(()));
+        assert_eq!(
+            require_json(Some("Application/JSON; charset=utf-8")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn other_media_types_are_415() {
+        for content_type in [None, Some("text/plain"), Some("application/jsonx")] {
+            let err = require_json(content_type).unwrap_err();
+
+            assert_eq!(err.status(), 415);
+            assert_eq!(
+                body(&err),
+                json!({
+                    "code": "unsupported_media_type",
+                    "message": "Content-Type must be application/json",
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn announced_body_over_the_limit_is_413_before_reading() {
+        let err = read_body(Some(2048), 1024, |_| panic!("the body must not be read"))
+            .unwrap_err()
+            .downcast::<ApiError>()
+            .unwrap();
+
+        assert_eq!(err.status(), 413);
+        assert_eq!(
+            body(&err),
+            json!({
+                "code": "payload_too_large",
+                "message": "body cannot be bigger than 1024 bytes",
+            })
+        );
+    }
+
+    #[test]
+    fn streamed_body_over_the_limit_is_413() {
+        let data = vec![b'a'; 600];
+
+        let err = read_body(None, 512, reader(&data, 100))
+            .unwrap_err()
+            .downcast::<ApiError>()
+            .unwrap();
+
+        assert_eq!(err.status(), 413);
+        assert_eq!(body(&err)["code"], "payload_too_large");
+    }
+
+    #[test]
+    fn body_is_read_in_chunks() {
+        let data: Vec<u8> = (0..=255).cycle().take(700).collect();
+
+        assert_eq!(read_body(Some(700), 700, reader(&data, 300)).unwrap(), data);
+    }
+
+    #[test]
+    fn invalid_json_is_400() {
+        let err = parse_json::<serde_json::Value>(b"{\"ssid\": ").unwrap_err();
+
+        assert_eq!(err.status(), 400);
+        assert_eq!(err.code(), "invalid_json");
+        assert_eq!(err.field(), None);
+        assert!(body(&err)["message"].as_str().is_some());
+        assert!(body(&err).get("field").is_none());
+    }
+
+    #[test]
+    fn invalid_field_is_400_with_the_field() {
+        let err = ApiError::from(CredentialsError::PasswordTooShort);
+
+        assert_eq!(err.status(), 400);
+        assert_eq!(
+            body(&err),
+            json!({
+                "code": "invalid_field",
+                "message": "password must have at least 8 characters or be empty",
+                "field": "password",
+            })
+        );
+    }
+
+    #[test]
+    fn internal_errors_do_not_leak_details() {
+        let err = ApiError::internal();
+
+        assert_eq!(err.status(), 500);
+        assert_eq!(
+            body(&err),
+            json!({ "code": "internal", "message": "internal error" })
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComboEvent {
+    /// Index of the combo whose buttons were all pressed within the window
+    Combo(usize),
+    /// Changes of buttons that did not end in a combo, with the time they happened at
+    Press(usize, u64),
+    Release(usize, u64),
+}
+
+/// Holds back the presses of buttons that are part of a combo until the combo window tells
+/// whether they were pressed alone or together. Buttons are indexes, timestamps microseconds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComboDetector {
+    combos: Vec<Vec<usize>>,
+    window_us: u64,
+    pending: Vec<(usize, u64)>,
+    // Held buttons of a combo that was sent, their release is swallowed too
+    consumed: Vec<usize>,
+}
+
+impl ComboDetector {
+    pub fn new(combos: Vec<Vec<usize>>, window: Duration) -> Self {
+        ComboDetector {
+            combos,
+            window_us: window.as_micros() as u64,
+            pending: vec![],
+            consumed: vec![],
+        }
+    }
+
+    fn in_combo(&self, button: usize) -> bool {
+        self.combos.iter().any(|combo| combo.contains(&button))
+    }
+
+    fn is_pending(&self, button: usize) -> bool {
+        self.pending.iter().any(|(pending, _)| *pending == button)
+    }
+
+    /// Biggest combo whose buttons are all pending
+    fn matched(&self) -> Option<usize> {
+        self.combos
+            .iter()
+            .enumerate()
+            .filter(|(_, combo)| combo.iter().all(|button| self.is_pending(*button)))
+            .max_by_key(|(_, combo)| combo.len())
+            .map(|(index, _)| index)
+    }
+
+    /// True when a bigger combo could still be completed by the buttons held so far
+    fn may_grow(&self, matched: usize) -> bool {
+        let size = self.combos[matched].len();
+
+        self.combos.iter().any(|combo| {
+            combo.len() > size
+                && self
+                    .pending
+                    .iter()
+                    .all(|(button, _)| combo.contains(button))
+        })
+    }
+
+    /// Sends the matched combo if any and replays the presses left over, in the order
+    /// they happened, the combo taking the place of its first button
+    fn flush(&mut self) -> Vec<ComboEvent> {
+        let combo = self
+            .matched()
+            .map(|matched| (matched, self.combos[matched].clone()));
+        let mut sent = false;
+        let mut events = vec![];
+
+        for (button, at) in self.pending.drain(..) {
+            match &combo {
+                Some((matched, combo)) if combo.contains(&button) => {
+                    if !sent {
+                        events.push(ComboEvent::Combo(*matched));
+                        sent = true;
+                    }
+                    self.consumed.push(button);
+                }
+                _ => events.push(ComboEvent::Press(button, at)),
+            }
+        }
+
+        events
+    }
+
+    pub fn press(&mut self, button: usize, at: u64) -> Vec<ComboEvent> {
+        if !self.in_combo(button) {
+            // Ends the window, the held presses go out ahead of it
+            let mut events = self.flush();
+            events.push(ComboEvent::Press(button, at));
+            return events;
+        }
+
+        self.pending.push((button, at));
+
+        match self.matched() {
+            Some(matched) if !self.may_grow(matched) => self.flush(),
+            _ => vec![],
+        }
+    }
+
+    pub fn release(&mut self, button: usize, at: u64) -> Vec<ComboEvent> {
+        let mut events = vec![];
+
+        // Released inside the window, it was not part of a combo or the combo is complete
+        if self.is_pending(button) {
+            events = self.flush();
+        }
+
+        if let Some(position) = self.consumed.iter().position(|held| *held == button) {
+            self.consumed.swap_remove(position);
+            return events;
+        }
+
+        events.push(ComboEvent::Release(button, at));
+
+        events
+    }
+
+    pub fn change(&mut self, button: usize, pressed: bool, at: u64) -> Vec<ComboEvent> {
+        if pressed {
+            self.press(button, at)
+        } else {
+            self.release(button, at)
+        }
+    }
+
+    /// Resolves the held presses once the window of the first one is over
+    pub fn poll(&mut self, now: u64) -> Vec<ComboEvent> {
+        match self.deadline() {
+            Some(deadline) if now >= deadline => self.flush(),
+            _ => vec![],
+        }
+    }
+
+    pub fn deadline(&self) -> Option<u64> {
+        self.pending.first().map(|(_, at)| at + self.window_us)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn chord_inside_the_window_is_a_combo() {
+        let mut detector = ComboDetector::new(vec![vec![0, 1]], WINDOW);
+
+        assert_eq!(detector.press(1, 0), []);
+        assert_eq!(detector.press(0, 10_000), [ComboEvent::Combo(0)]);
+        assert_eq!(detector.deadline(), None);
+
+        // Both releases belong to the combo
+        assert_eq!(detector.release(0, 100_000), []);
+        assert_eq!(detector.release(1, 110_000), []);
+        assert_eq!(
+            detector.release(1, 120_000),
+            [ComboEvent::Release(1, 120_000)]
+        );
+    }
+
+    #[test]
+    fn chord_timeout_replays_the_presses_in_order() {
+        let mut detector = ComboDetector::new(vec![vec![0, 1, 2]], WINDOW);
+
+        detector.press(2, 0);
+        detector.press(0, 10_000);
+        assert_eq!(detector.deadline(), Some(50_000));
+        assert_eq!(detector.poll(49_999), []);
+        assert_eq!(
+            detector.poll(50_000),
+            [ComboEvent::Press(2, 0), ComboEvent::Press(0, 10_000)]
+        );
+        assert_eq!(
+            detector.release(2, 60_000),
+            [ComboEvent::Release(2, 60_000)]
+        );
+    }
+
+    #[test]
+    fn partial_release_replays_the_presses_before_it() {
+        let mut detector = ComboDetector::new(vec![vec![0, 1, 2]], WINDOW);
+
+        detector.press(0, 0);
+        detector.press(1, 5_000);
+        assert_eq!(
+            detector.release(0, 10_000),
+            [
+                ComboEvent::Press(0, 0),
+                ComboEvent::Press(1, 5_000),
+                ComboEvent::Release(0, 10_000),
+            ]
+        );
+        assert_eq!(detector.deadline(), None);
+        assert_eq!(
+            detector.release(1, 20_000),
+            [ComboEvent::Release(1, 20_000)]
+        );
+    }
+
+    #[test]
+    fn press_outside_combos_goes_after_the_pending_ones() {
+        let mut detector = ComboDetector::new(vec![vec![0, 1]], WINDOW);
+
+        detector.press(0, 0);
+        assert_eq!(
+            detector.press(5, 1_000),
+            [ComboEvent::Press(0, 0), ComboEvent::Press(5, 1_000)]
+        );
+        assert_eq!(detector.press(6, 2_000), [ComboEvent::Press(6, 2_000)]);
+    }
+
+    #[test]
+    fn combo_keeps_its_place_among_the_presses() {
+        let mut detector = ComboDetector::new(vec![vec![0, 3], vec![1, 2]], WINDOW);
+
+        detector.press(0, 0);
+        detector.press(1, 1_000);
+        // 0 cannot be part of a bigger combo with 1 and 2, it is replayed first
+        assert_eq!(
+            detector.press(2, 2_000),
+            [ComboEvent::Press(0, 0), ComboEvent::Combo(1)]
+        );
+    }
+
+    #[test]
+    fn bigger_overlapping_combo_wins_inside_the_window() {
+        let combos = vec![vec![0, 1], vec![0, 1, 2]];
+
+        let mut detector = ComboDetector::new(combos.clone(), WINDOW);
+        assert_eq!(detector.press(0, 0), []);
+        assert_eq!(detector.press(1, 1_000), []);
+        assert_eq!(detector.press(2, 2_000), [ComboEvent::Combo(1)]);
+
+        // The smaller one once the window is over
+        let mut detector = ComboDetector::new(combos, WINDOW);
+        detector.press(0, 0);
+        detector.press(1, 1_000);
+        assert_eq!(detector.poll(50_000), [ComboEvent::Combo(0)]);
+        assert_eq!(detector.press(2, 60_000), []);
+        assert_eq!(detector.poll(110_000), [ComboEvent::Press(2, 60_000)]);
+    }
+
+    #[test]
+    fn combos_sharing_a_button() {
+        let mut detector = ComboDetector::new(vec![vec![0, 1], vec![1, 2]], WINDOW);
+
+        assert_eq!(detector.press(0, 0), []);
+        assert_eq!(detector.press(1, 1_000), [ComboEvent::Combo(0)]);
+        assert_eq!(detector.press(2, 2_000), []);
+        assert_eq!(detector.poll(52_000), [ComboEvent::Press(2, 2_000)]);
+        assert_eq!(detector.release(1, 60_000), []);
+    }
+}
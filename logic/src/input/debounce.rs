@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+/// Debounces the edges of one button, timestamps are microseconds from any monotonic clock.
+///
+/// A change is reported as soon as its first edge arrives, then the button is locked for the
+/// debounce window so the bounces that follow are ignored. When the window closes the last
+/// level seen is compared again, this way a tap shorter than the window is still reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Debouncer {
+    window_us: u64,
+    stable: bool,
+    raw: bool,
+    locked_until: Option<u64>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration, pressed: bool) -> Self {
+        Debouncer {
+            window_us: window.as_micros() as u64,
+            stable: pressed,
+            raw: pressed,
+            locked_until: None,
+        }
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.stable
+    }
+
+    /// Feeds the level read after an edge, returns the new state when it changes
+    pub fn edge(&mut self, pressed: bool, at: u64) -> Option<bool> {
+        self.raw = pressed;
+        self.settle(at)
+    }
+
+    /// Reports a change held back by the window once it has elapsed
+    pub fn settle(&mut self, now: u64) -> Option<bool> {
+        if let Some(until) = self.locked_until {
+            if now < until {
+                return None;
+            }
+            self.locked_until = None;
+        }
+
+        if self.raw == self.stable {
+            return None;
+        }
+
+        self.stable = self.raw;
+        self.locked_until = Some(now + self.window_us);
+
+        Some(self.stable)
+    }
+
+    /// When settle has to be called again to report a pending change, None if nothing is pending
+    pub fn deadline(&self) -> Option<u64> {
+        if self.raw == self.stable {
+            return None;
+        }
+
+        self.locked_until
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_millis(20);
+
+    #[test]
+    fn bounce_inside_the_window_is_ignored() {
+        let mut debouncer = Debouncer::new(WINDOW, false);
+
+        assert_eq!(debouncer.edge(true, 1_000), Some(true));
+        assert_eq!(debouncer.edge(false, 1_500), None);
+        assert_eq!(debouncer.edge(true, 2_000), None);
+        assert_eq!(debouncer.settle(21_000), None);
+        assert!(debouncer.is_pressed());
+    }
+
+    #[test]
+    fn tap_shorter_than_the_window_is_reported_on_settle() {
+        let mut debouncer = Debouncer::new(WINDOW, false);
+
+        assert_eq!(debouncer.edge(true, 0), Some(true));
+        assert_eq!(debouncer.edge(false, 5_000), None);
+        assert_eq!(debouncer.deadline(), Some(20_000));
+        assert_eq!(debouncer.settle(19_999), None);
+        assert_eq!(debouncer.settle(20_000), Some(false));
+        assert!(!debouncer.is_pressed());
+    }
+
+    #[test]
+    fn deadline_is_none_when_nothing_is_pending() {
+        let mut debouncer = Debouncer::new(WINDOW, false);
+        assert_eq!(debouncer.deadline(), None);
+
+        // Locked but at the level already reported
+        debouncer.edge(true, 0);
+        assert_eq!(debouncer.deadline(), None);
+
+        debouncer.edge(false, 1_000);
+        debouncer.edge(true, 2_000);
+        assert_eq!(debouncer.deadline(), None);
+    }
+
+    #[test]
+    fn edge_after_the_window_is_reported_right_away() {
+        let mut debouncer = Debouncer::new(WINDOW, true);
+
+        assert_eq!(debouncer.edge(false, 0), Some(false));
+        assert_eq!(debouncer.edge(true, 25_000), Some(true));
+    }
+}
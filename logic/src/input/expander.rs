@@ -0,0 +1,241 @@
+/// I2C side of an expander, addresses are 7 bits
+pub trait I2cBus {
+    fn write(&mut self, address: u8, bytes: &[u8]) -> anyhow::Result<()>;
+    fn read(&mut self, address: u8, buffer: &mut [u8]) -> anyhow::Result<()>;
+    fn write_read(&mut self, address: u8, bytes: &[u8], buffer: &mut [u8]) -> anyhow::Result<()>;
+}
+
+// MCP23017 registers with IOCON.BANK = 0, port B follows port A
+const MCP_IODIRA: u8 = 0x00;
+const MCP_GPINTENA: u8 = 0x04;
+const MCP_IOCON: u8 = 0x0a;
+const MCP_GPPUA: u8 = 0x0c;
+const MCP_GPIOA: u8 = 0x12;
+// INT pins mirrored and open drain, so one line with a pull-up serves both ports
+const MCP_IOCON_MIRROR: u8 = 1 << 6;
+const MCP_IOCON_ODR: u8 = 1 << 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpanderKind {
+    /// 16 pins, pull-ups and interrupt on change set through registers
+    Mcp23017,
+    /// 8 quasi-bidirectional pins, inputs are pins written high
+    Pcf8574,
+}
+
+impl ExpanderKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "mcp23017" => Some(ExpanderKind::Mcp23017),
+            "pcf8574" => Some(ExpanderKind::Pcf8574),
+            _ => None,
+        }
+    }
+
+    pub fn pins(&self) -> usize {
+        match self {
+            ExpanderKind::Mcp23017 => 16,
+            ExpanderKind::Pcf8574 => 8,
+        }
+    }
+}
+
+/// Buttons wired between the pins of an expander and ground, pin n is bit n of port A then B
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expander {
+    kind: ExpanderKind,
+    address: u8,
+    state: u16,
+}
+
+impl Expander {
+    pub fn new(kind: ExpanderKind, address: u8) -> Self {
+        Expander {
+            kind,
+            address,
+            state: 0,
+        }
+    }
+
+    pub fn kind(&self) -> ExpanderKind {
+        self.kind
+    }
+
+    pub fn is_pressed(&self, pin: usize) -> bool {
+        pin < self.kind.pins() && self.state & (1 << pin) != 0
+    }
+
+    /// Makes every pin an input pulled up that raises the interrupt line when it changes
+    pub fn init<B: I2cBus>(&mut self, bus: &mut B) -> anyhow::Result<()> {
+        match self.kind {
+            ExpanderKind::Mcp23017 => {
+                bus.write(self.address, &[MCP_IOCON, MCP_IOCON_MIRROR | MCP_IOCON_ODR])?;
+                bus.write(self.address, &[MCP_IODIRA, 0xff, 0xff])?;
+                bus.write(self.address, &[MCP_GPPUA, 0xff, 0xff])?;
+                bus.write(self.address, &[MCP_GPINTENA, 0xff, 0xff])?;
+            }
+            ExpanderKind::Pcf8574 => bus.write(self.address, &[0xff])?,
+        }
+
+        // Buttons held at boot are reported by the first poll
+        self.state = 0;
+
+        Ok(())
+    }
+
+    /// Pressed pins as a bit mask, reading the pins also clears the interrupt
+    pub fn read<B: I2cBus>(&self, bus: &mut B) -> anyhow::Result<u16> {
+        let levels = match self.kind {
+            ExpanderKind::Mcp23017 => {
+                let mut ports = [0; 2];
+                bus.write_read(self.address, &[MCP_GPIOA], &mut ports)?;
+                u16::from_le_bytes(ports)
+            }
+            ExpanderKind::Pcf8574 => {
+                let mut port = [0; 1];
+                bus.read(self.address, &mut port)?;
+                u16::from(port[0]) | 0xff00
+            }
+        };
+
+        // Active low
+        Ok(!levels)
+    }
+
+    /// Reads the pins, returns the ones that changed as (pin, pressed)
+    pub fn poll<B: I2cBus>(&mut self, bus: &mut B) -> anyhow::Result<Vec<(usize, bool)>> {
+        let pressed = self.read(bus)?;
+        let changed = pressed ^ self.state;
+        self.state = pressed;
+
+        Ok((0..self.kind.pins())
+            .filter(|pin| changed & (1 << pin) != 0)
+            .map(|pin| (pin, pressed & (1 << pin) != 0))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    /// Records the writes and serves reads from its registers, a read without a register
+    /// (PCF8574) serves the register 0
+    #[derive(Default)]
+    struct FakeBus {
+        writes: Vec<(u8, Vec<u8>)>,
+        registers: HashMap<u8, u8>,
+    }
+
+    impl FakeBus {
+        fn serve(&self, register: u8, buffer: &mut [u8]) {
+            for (offset, byte) in buffer.iter_mut().enumerate() {
+                *byte = self.registers[&(register + offset as u8)];
+            }
+        }
+    }
+
+    impl I2cBus for FakeBus {
+        fn write(&mut self, address: u8, bytes: &[u8]) -> anyhow::Result<()> {
+            self.writes.push((address, bytes.to_vec()));
+            Ok(())
+        }
+
+        fn read(&mut self, _address: u8, buffer: &mut [u8]) -> anyhow::Result<()> {
+            self.serve(0, buffer);
+            Ok(())
+        }
+
+        fn write_read(
+            &mut self,
+            _address: u8,
+            bytes: &[u8],
+            buffer: &mut [u8],
+        ) -> anyhow::Result<()> {
+            let register = *bytes.first().ok_or_else(|| anyhow!("no register"))?;
+            self.serve(register, buffer);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn kind_from_name() {
+        assert_eq!(
+            ExpanderKind::from_name("MCP23017"),
+            Some(ExpanderKind::Mcp23017)
+        );
+        assert_eq!(
+            ExpanderKind::from_name("pcf8574"),
+            Some(ExpanderKind::Pcf8574)
+        );
+        assert_eq!(ExpanderKind::from_name("pca9555"), None);
+    }
+
+    #[test]
+    fn mcp23017_init_sequence() {
+        let mut bus = FakeBus::default();
+        let mut expander = Expander::new(ExpanderKind::Mcp23017, 0x20);
+
+        expander.init(&mut bus).unwrap();
+
+        assert_eq!(
+            bus.writes,
+            vec![
+                (0x20, vec![0x0a, 0x44]),
+                (0x20, vec![0x00, 0xff, 0xff]),
+                (0x20, vec![0x0c, 0xff, 0xff]),
+                (0x20, vec![0x04, 0xff, 0xff]),
+            ]
+        );
+    }
+
+    #[test]
+    fn pcf8574_init_makes_every_pin_an_input() {
+        let mut bus = FakeBus::default();
+        let mut expander = Expander::new(ExpanderKind::Pcf8574, 0x27);
+
+        expander.init(&mut bus).unwrap();
+
+        assert_eq!(bus.writes, vec![(0x27, vec![0xff])]);
+    }
+
+    #[test]
+    fn mcp23017_poll_reports_active_low_changes() {
+        let mut bus = FakeBus::default();
+        let mut expander = Expander::new(ExpanderKind::Mcp23017, 0x20);
+        expander.init(&mut bus).unwrap();
+
+        bus.registers.insert(MCP_GPIOA, 0xff);
+        bus.registers.insert(MCP_GPIOA + 1, 0xff);
+        assert!(expander.poll(&mut bus).unwrap().is_empty());
+
+        // Pin 1 of port A and pin 0 of port B pulled to ground
+        bus.registers.insert(MCP_GPIOA, 0b1111_1101);
+        bus.registers.insert(MCP_GPIOA + 1, 0b1111_1110);
+        assert_eq!(expander.poll(&mut bus).unwrap(), vec![(1, true), (8, true)]);
+        assert!(expander.is_pressed(1) && expander.is_pressed(8));
+        assert!(expander.poll(&mut bus).unwrap().is_empty());
+
+        bus.registers.insert(MCP_GPIOA, 0xff);
+        assert_eq!(expander.poll(&mut bus).unwrap(), vec![(1, false)]);
+        assert!(!expander.is_pressed(1) && expander.is_pressed(8));
+    }
+
+    #[test]
+    fn pcf8574_poll_reports_its_eight_pins() {
+        let mut bus = FakeBus::default();
+        let mut expander = Expander::new(ExpanderKind::Pcf8574, 0x27);
+        expander.init(&mut bus).unwrap();
+
+        bus.registers.insert(0, 0b0111_1111);
+        assert_eq!(expander.poll(&mut bus).unwrap(), vec![(7, true)]);
+        assert!(!expander.is_pressed(8));
+
+        bus.registers.insert(0, 0xff);
+        assert_eq!(expander.poll(&mut bus).unwrap(), vec![(7, false)]);
+    }
+}
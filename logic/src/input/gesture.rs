@@ -0,0 +1,323 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gesture {
+    /// Quick presses in a row, 1 is a single click and 2 a double click
+    Tap(u8),
+    LongPress,
+    /// Sent every repeat interval while the button stays held after a long press
+    Repeat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureTiming {
+    /// Longest release between two taps of the same sequence
+    pub tap_gap: Duration,
+    pub long_press: Duration,
+    pub repeat_interval: Option<Duration>,
+    /// Taps counted before the sequence is reported without waiting for the gap
+    pub max_taps: u8,
+    /// When false a long press is reported as a tap on release
+    pub detect_long_press: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Pressed {
+        since: u64,
+        taps: u8,
+        long_press: bool,
+        next_repeat: Option<u64>,
+    },
+    Released {
+        at: u64,
+        taps: u8,
+    },
+}
+
+/// Turns the debounced presses of one button into gestures, timestamps are microseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GestureRecognizer {
+    timing: GestureTiming,
+    state: State,
+}
+
+fn micros(duration: Duration) -> u64 {
+    duration.as_micros() as u64
+}
+
+impl GestureRecognizer {
+    pub fn new(timing: GestureTiming) -> Self {
+        GestureRecognizer {
+            timing: GestureTiming {
+                max_taps: timing.max_taps.max(1),
+                ..timing
+            },
+            state: State::Idle,
+        }
+    }
+
+    pub fn press(&mut self, at: u64) -> Option<Gesture> {
+        let (taps, reported) = match self.state {
+            State::Released { at: released, taps }
+                if at.saturating_sub(released) <= micros(self.timing.tap_gap) =>
+            {
+                (taps + 1, None)
+            }
+            // A sequence left waiting is over, report it before starting a new one
+            State::Released { taps, .. } => (1, Some(Gesture::Tap(taps))),
+            _ => (1, None),
+        };
+
+        self.state = State::Pressed {
+            since: at,
+            taps,
+            long_press: false,
+            next_repeat: None,
+        };
+
+        reported
+    }
+
+    pub fn release(&mut self, at: u64) -> Option<Gesture> {
+        let State::Pressed {
+            since,
+            taps,
+            long_press,
+            ..
+        } = self.state
+        else {
+            return None;
+        };
+
+        if long_press {
+            self.state = State::Idle;
+            return None;
+        }
+
+        // Held for the threshold without a poll in between, reported as poll would have
+        if self.timing.detect_long_press
+            && at.saturating_sub(since) >= micros(self.timing.long_press)
+        {
+            self.state = State::Idle;
+            return (taps == 1).then_some(Gesture::LongPress);
+        }
+
+        if taps >= self.timing.max_taps {
+            self.state = State::Idle;
+            return Some(Gesture::Tap(taps));
+        }
+
+        self.state = State::Released { at, taps };
+
+        None
+    }
+
+    /// Reports the gestures that depend on time alone, call it again at the deadline
+    pub fn poll(&mut self, now: u64) -> Option<Gesture> {
+        match self.state {
+            State::Released { at, taps }
+                if now.saturating_sub(at) > micros(self.timing.tap_gap) =>
+            {
+                self.state = State::Idle;
+                Some(Gesture::Tap(taps))
+            }
+            State::Pressed {
+                since,
+                taps,
+                long_press: false,
+                ..
+            } if self.timing.detect_long_press
+                && now.saturating_sub(since) >= micros(self.timing.long_press) =>
+            {
+                if taps > 1 {
+                    // Held on a later tap, the sequence is dropped
+                    self.state = State::Pressed {
+                        since,
+                        taps,
+                        long_press: true,
+                        next_repeat: None,
+                    };
+                    return None;
+                }
+
+                self.state = State::Pressed {
+                    since,
+                    taps,
+                    long_press: true,
+                    next_repeat: self
+                        .timing
+                        .repeat_interval
+                        .map(|interval| now + micros(interval)),
+                };
+
+                Some(Gesture::LongPress)
+            }
+            State::Pressed {
+                since,
+                taps,
+                long_press: true,
+                next_repeat: Some(next_repeat),
+            } if now >= next_repeat => {
+                let interval = self.timing.repeat_interval.map(micros).unwrap_or_default();
+
+                // From now and not from the last deadline, a late poll does not send a burst
+                self.state = State::Pressed {
+                    since,
+                    taps,
+                    long_press: true,
+                    next_repeat: Some(now + interval),
+                };
+
+                Some(Gesture::Repeat)
+            }
+            _ => None,
+        }
+    }
+
+    /// When poll has something to report, None while nothing depends on time
+    pub fn deadline(&self) -> Option<u64> {
+        match self.state {
+            State::Idle => None,
+            State::Released { at, .. } => Some(at + micros(self.timing.tap_gap) + 1),
+            State::Pressed {
+                since,
+                long_press: false,
+                ..
+            } if self.timing.detect_long_press => Some(since + micros(self.timing.long_press)),
+            State::Pressed { next_repeat, .. } => next_repeat,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMING: GestureTiming = GestureTiming {
+        tap_gap: Duration::from_millis(250),
+        long_press: Duration::from_millis(500),
+        repeat_interval: None,
+        max_taps: 3,
+        detect_long_press: true,
+    };
+
+    #[test]
+    fn single_tap_is_reported_once_the_gap_expires() {
+        let mut recognizer = GestureRecognizer::new(TIMING);
+
+        assert_eq!(recognizer.press(0), None);
+        assert_eq!(recognizer.release(100_000), None);
+        assert_eq!(recognizer.deadline(), Some(350_001));
+        assert_eq!(recognizer.poll(350_000), None);
+        assert_eq!(recognizer.poll(350_001), Some(Gesture::Tap(1)));
+        assert_eq!(recognizer.deadline(), None);
+    }
+
+    #[test]
+    fn double_tap() {
+        let mut recognizer = GestureRecognizer::new(TIMING);
+
+        recognizer.press(0);
+        recognizer.release(50_000);
+        assert_eq!(recognizer.press(200_000), None);
+        assert_eq!(recognizer.release(250_000), None);
+        assert_eq!(recognizer.poll(400_000), None);
+        assert_eq!(recognizer.poll(500_001), Some(Gesture::Tap(2)));
+    }
+
+    #[test]
+    fn tap_after_the_gap_starts_a_new_sequence() {
+        let mut recognizer = GestureRecognizer::new(TIMING);
+
+        recognizer.press(0);
+        recognizer.release(50_000);
+        // Nobody polled at the deadline, the late press reports the first tap
+        assert_eq!(recognizer.press(400_000), Some(Gesture::Tap(1)));
+        recognizer.release(450_000);
+        assert_eq!(recognizer.poll(700_001), Some(Gesture::Tap(1)));
+    }
+
+    #[test]
+    fn max_taps_is_reported_on_release() {
+        let mut recognizer = GestureRecognizer::new(TIMING);
+
+        for tap in 0..2 {
+            recognizer.press(tap * 100_000);
+            assert_eq!(recognizer.release(tap * 100_000 + 50_000), None);
+        }
+        recognizer.press(200_000);
+        assert_eq!(recognizer.release(250_000), Some(Gesture::Tap(3)));
+        assert_eq!(recognizer.deadline(), None);
+    }
+
+    #[test]
+    fn long_press_fires_before_release() {
+        let mut recognizer = GestureRecognizer::new(TIMING);
+
+        recognizer.press(1_000);
+        assert_eq!(recognizer.deadline(), Some(501_000));
+        assert_eq!(recognizer.poll(500_999), None);
+        assert_eq!(recognizer.poll(501_000), Some(Gesture::LongPress));
+        assert_eq!(recognizer.poll(900_000), None);
+        assert_eq!(recognizer.release(1_000_000), None);
+        assert_eq!(recognizer.poll(2_000_000), None);
+    }
+
+    #[test]
+    fn long_press_repeats_while_held() {
+        let mut recognizer = GestureRecognizer::new(GestureTiming {
+            repeat_interval: Some(Duration::from_millis(100)),
+            ..TIMING
+        });
+
+        recognizer.press(0);
+        assert_eq!(recognizer.poll(500_000), Some(Gesture::LongPress));
+        assert_eq!(recognizer.deadline(), Some(600_000));
+        assert_eq!(recognizer.poll(600_000), Some(Gesture::Repeat));
+        // Late poll, the next repeat is counted from now
+        assert_eq!(recognizer.poll(950_000), Some(Gesture::Repeat));
+        assert_eq!(recognizer.deadline(), Some(1_050_000));
+        assert_eq!(recognizer.release(1_000_000), None);
+        assert_eq!(recognizer.deadline(), None);
+    }
+
+    #[test]
+    fn release_exactly_at_the_threshold_is_a_long_press() {
+        let mut recognizer = GestureRecognizer::new(TIMING);
+
+        recognizer.press(0);
+        assert_eq!(recognizer.release(500_000), Some(Gesture::LongPress));
+        assert_eq!(recognizer.poll(1_000_000), None);
+
+        recognizer.press(2_000_000);
+        assert_eq!(recognizer.release(2_499_999), None);
+        assert_eq!(recognizer.poll(2_750_000), Some(Gesture::Tap(1)));
+    }
+
+    #[test]
+    fn tap_then_long_press_drops_the_sequence() {
+        let mut recognizer = GestureRecognizer::new(TIMING);
+
+        recognizer.press(0);
+        recognizer.release(50_000);
+        assert_eq!(recognizer.press(200_000), None);
+        assert_eq!(recognizer.poll(700_000), None);
+        assert_eq!(recognizer.release(800_000), None);
+        assert_eq!(recognizer.poll(2_000_000), None);
+    }
+
+    #[test]
+    fn long_press_detection_off_reports_a_tap() {
+        let mut recognizer = GestureRecognizer::new(GestureTiming {
+            detect_long_press: false,
+            ..TIMING
+        });
+
+        recognizer.press(0);
+        assert_eq!(recognizer.poll(1_000_000), None);
+        assert_eq!(recognizer.release(1_000_000), None);
+        assert_eq!(recognizer.poll(1_250_001), Some(Gesture::Tap(1)));
+    }
+}
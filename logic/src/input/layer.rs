@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+/// What a layer key does, layer 0 is the actions map of the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerKey {
+    /// The layer is active while the key is held
+    Momentary(u8),
+    /// Each press switches to the layer or back to layer 0
+    Toggle(u8),
+}
+
+/// Active layer of the buttons, out of range layers are refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LayerState {
+    count: usize,
+    base: usize,
+    // Held momentary keys, the last one pressed wins
+    held: Vec<usize>,
+}
+
+impl LayerState {
+    /// `count` includes layer 0
+    pub fn new(count: usize) -> Self {
+        LayerState {
+            count,
+            base: 0,
+            held: vec![],
+        }
+    }
+
+    pub fn active(&self) -> usize {
+        self.held.last().copied().unwrap_or(self.base)
+    }
+
+    /// Sets the layer used while no momentary key is held
+    pub fn set_base(&mut self, layer: usize) -> bool {
+        if layer >= self.count {
+            return false;
+        }
+
+        self.base = layer;
+        true
+    }
+
+    pub fn key(&mut self, key: LayerKey, pressed: bool) -> bool {
+        match key {
+            LayerKey::Momentary(layer) => {
+                let layer = usize::from(layer);
+                if layer >= self.count {
+                    return false;
+                }
+
+                if pressed {
+                    self.held.push(layer);
+                } else if let Some(position) = self.held.iter().rposition(|held| *held == layer) {
+                    self.held.remove(position);
+                }
+
+                true
+            }
+            LayerKey::Toggle(layer) if pressed => {
+                let layer = usize::from(layer);
+                let target = if self.base == layer { 0 } else { layer };
+
+                self.set_base(target)
+            }
+            LayerKey::Toggle(_) => true,
+        }
+    }
+}
+
+/// Layer a button was last pressed on, its release is sent on the same one even when the
+/// active layer changed while it was held
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PressLayer(usize);
+
+impl PressLayer {
+    /// Takes `active` on a press, returns the layer the change is sent on
+    pub fn update(&mut self, pressed: bool, active: usize) -> usize {
+        if pressed {
+            self.0 = active;
+        }
+
+        self.0
+    }
+
+    pub fn get(&self) -> usize {
+        self.0
+    }
+}
+
+/// Value of a button on `layer`, the layers that leave it out fall through to layer 0
+pub fn resolve<T>(values: &[Option<T>], layer: usize) -> Option<&T> {
+    values
+        .get(layer)
+        .and_then(Option::as_ref)
+        .or_else(|| values.first().and_then(Option::as_ref))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn momentary_layer_is_active_while_held() {
+        let mut layers = LayerState::new(3);
+
+        assert!(layers.key(LayerKey::Momentary(1), true));
+        assert_eq!(layers.active(), 1);
+        assert!(layers.key(LayerKey::Momentary(2), true));
+        assert_eq!(layers.active(), 2);
+
+        // The first key released, the last one pressed still wins
+        assert!(layers.key(LayerKey::Momentary(1), false));
+        assert_eq!(layers.active(), 2);
+        assert!(layers.key(LayerKey::Momentary(2), false));
+        assert_eq!(layers.active(), 0);
+    }
+
+    #[test]
+    fn toggle_layer_stays_until_pressed_again() {
+        let mut layers = LayerState::new(3);
+
+        assert!(layers.key(LayerKey::Toggle(2), true));
+        assert!(layers.key(LayerKey::Toggle(2), false));
+        assert_eq!(layers.active(), 2);
+
+        // A momentary key goes over the locked layer and back to it
+        layers.key(LayerKey::Momentary(1), true);
+        assert_eq!(layers.active(), 1);
+        layers.key(LayerKey::Momentary(1), false);
+        assert_eq!(layers.active(), 2);
+
+        layers.key(LayerKey::Toggle(2), true);
+        assert_eq!(layers.active(), 0);
+    }
+
+    #[test]
+    fn out_of_range_layers_are_refused() {
+        let mut layers = LayerState::new(2);
+
+        assert!(!layers.key(LayerKey::Momentary(2), true));
+        assert!(!layers.key(LayerKey::Toggle(5), true));
+        assert!(!layers.set_base(2));
+        assert_eq!(layers.active(), 0);
+    }
+
+    #[test]
+    fn missing_actions_fall_through_to_layer_0() {
+        let values = [Some("base"), None, Some("two")];
+
+        assert_eq!(resolve(&values, 0), Some(&"base"));
+        assert_eq!(resolve(&values, 1), Some(&"base"));
+        assert_eq!(resolve(&values, 2), Some(&"two"));
+        assert_eq!(resolve(&values, 7), Some(&"base"));
+        assert_eq!(resolve::<&str>(&[None, None], 1), None);
+    }
+
+    #[test]
+    fn release_uses_the_layer_of_the_press() {
+        let mut layers = LayerState::new(2);
+        let mut button = PressLayer::default();
+
+        layers.key(LayerKey::Momentary(1), true);
+        assert_eq!(button.update(true, layers.active()), 1);
+
+        layers.key(LayerKey::Momentary(1), false);
+        assert_eq!(layers.active(), 0);
+        assert_eq!(button.update(false, layers.active()), 1);
+
+        assert_eq!(button.update(true, layers.active()), 0);
+        assert_eq!(button.get(), 0);
+    }
+}
@@ -0,0 +1,292 @@
+use jojo_common::button::{ButtonAction, ButtonMode};
+use serde::{Deserialize, Serialize};
+
+use super::mode::actions_for;
+
+// Steps run in one go before the scheduler gets the thread back, bounds macros without delays
+const MAX_STEPS_PER_RUN: usize = 64;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MacroStep {
+    /// Sends the actions as they are
+    Send(Vec<ButtonAction>),
+    /// Waits before the next step, in milliseconds
+    Delay(u32),
+    /// Sends the down state of the actions and their up state `ms` later
+    Hold { actions: Vec<ButtonAction>, ms: u32 },
+    /// Runs the steps `count` times
+    Repeat { count: u16, steps: Vec<MacroStep> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    index: usize,
+    remaining: u16,
+}
+
+/// Walks the nested repeats down to the list of steps the innermost frame is in
+fn steps_at<'a>(root: &'a [MacroStep], frames: &[Frame]) -> &'a [MacroStep] {
+    let mut steps = root;
+
+    for frame in frames {
+        steps = match steps.get(frame.index) {
+            Some(MacroStep::Repeat { steps, .. }) => steps,
+            _ => &[],
+        };
+    }
+
+    steps
+}
+
+/// Runs a macro against a clock, timestamps are microseconds.
+///
+/// Delays are counted from when the previous step was due and not from when it ran,
+/// so a late run does not stretch the macro.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MacroRunner {
+    stack: Vec<Frame>,
+    wake_at: Option<u64>,
+    release: Option<Vec<ButtonAction>>,
+}
+
+impl MacroRunner {
+    pub fn start(&mut self) {
+        self.stack = vec![Frame {
+            index: 0,
+            remaining: 0,
+        }];
+        self.wake_at = None;
+        self.release = None;
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.stack.is_empty() || self.release.is_some()
+    }
+
+    /// When run has to be called again, None when the macro is over
+    pub fn deadline(&self) -> Option<u64> {
+        self.wake_at
+    }
+
+    /// Runs the steps that are due, returns the batches of actions to send in order
+    pub fn run(&mut self, root: &[MacroStep], now: u64) -> Vec<Vec<ButtonAction>> {
+        let mut batches = vec![];
+        let mut clock = now;
+
+        if let Some(wake_at) = self.wake_at {
+            if now < wake_at {
+                return batches;
+            }
+            clock = wake_at;
+            self.wake_at = None;
+        }
+
+        if let Some(actions) = self.release.take() {
+            batches.push(actions_for(&ButtonMode::Hold, &actions, false));
+        }
+
+        for _ in 0..MAX_STEPS_PER_RUN {
+            let Some(depth) = self.stack.len().checked_sub(1) else {
+                return batches;
+            };
+
+            let steps = steps_at(root, &self.stack[..depth]);
+            let frame = &mut self.stack[depth];
+
+            let Some(step) = steps.get(frame.index) else {
+                if frame.remaining > 0 {
+                    frame.remaining -= 1;
+                    frame.index = 0;
+                } else {
+                    self.stack.pop();
+                    if let Some(parent) = self.stack.last_mut() {
+                        parent.index += 1;
+                    }
+                }
+                continue;
+            };
+
+            match step {
+                MacroStep::Send(actions) => {
+                    frame.index += 1;
+                    batches.push(actions.clone());
+                }
+                MacroStep::Delay(ms) => {
+                    frame.index += 1;
+                    self.wake_at = Some(clock + u64::from(*ms) * 1000);
+                    return batches;
+                }
+                MacroStep::Hold { actions, ms } => {
+                    frame.index += 1;
+                    batches.push(actions_for(&ButtonMode::Hold, actions, true));
+                    self.release = Some(actions.clone());
+                    self.wake_at = Some(clock + u64::from(*ms) * 1000);
+                    return batches;
+                }
+                MacroStep::Repeat { count, steps } if *count == 0 || steps.is_empty() => {
+                    frame.index += 1;
+                }
+                MacroStep::Repeat { count, .. } => {
+                    let remaining = count - 1;
+                    self.stack.push(Frame {
+                        index: 0,
+                        remaining,
+                    });
+                }
+            }
+        }
+
+        // Out of budget, the rest is due right away
+        self.wake_at = Some(clock);
+
+        batches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jojo_common::mouse::{MouseButton, MouseButtonState};
+
+    use super::*;
+
+    fn down(button: MouseButton) -> ButtonAction {
+        ButtonAction::MouseButton(button, MouseButtonState::Down)
+    }
+
+    fn started() -> MacroRunner {
+        let mut runner = MacroRunner::default();
+        runner.start();
+        runner
+    }
+
+    #[test]
+    fn delay_is_counted_from_the_due_time() {
+        let steps = [
+            MacroStep::Send(vec![down(MouseButton::Left)]),
+            MacroStep::Delay(10),
+            MacroStep::Delay(10),
+            MacroStep::Send(vec![down(MouseButton::Right)]),
+        ];
+        let mut runner = started();
+
+        assert_eq!(runner.run(&steps, 0), vec![vec![down(MouseButton::Left)]]);
+        assert_eq!(runner.deadline(), Some(10_000));
+
+        assert!(runner.run(&steps, 5_000).is_empty());
+        assert_eq!(runner.deadline(), Some(10_000));
+
+        // Late by 3ms, the second delay still ends 20ms after the start
+        assert!(runner.run(&steps, 13_000).is_empty());
+        assert_eq!(runner.deadline(), Some(20_000));
+
+        assert_eq!(
+            runner.run(&steps, 20_500),
+            vec![vec![down(MouseButton::Right)]]
+        );
+        assert_eq!(runner.deadline(), None);
+        assert!(!runner.is_running());
+    }
+
+    #[test]
+    fn hold_releases_the_actions_after_its_time() {
+        let steps = [MacroStep::Hold {
+            actions: vec![down(MouseButton::Left)],
+            ms: 50,
+        }];
+        let mut runner = started();
+
+        assert_eq!(
+            runner.run(&steps, 0),
+            vec![vec![ButtonAction::MouseButton(
+                MouseButton::Left,
+                MouseButtonState::Down
+            )]]
+        );
+        assert_eq!(runner.deadline(), Some(50_000));
+        assert!(runner.is_running());
+
+        assert_eq!(
+            runner.run(&steps, 50_000),
+            vec![vec![ButtonAction::MouseButton(
+                MouseButton::Left,
+                MouseButtonState::Up
+            )]]
+        );
+        assert!(!runner.is_running());
+    }
+
+    #[test]
+    fn nested_repeats_run_count_times_each() {
+        let (a, b) = (down(MouseButton::Left), down(MouseButton::Right));
+        let steps = [MacroStep::Repeat {
+            count: 2,
+            steps: vec![
+                MacroStep::Send(vec![a.clone()]),
+                MacroStep::Repeat {
+                    count: 3,
+                    steps: vec![MacroStep::Send(vec![b.clone()])],
+                },
+            ],
+        }];
+        let mut runner = started();
+
+        let sent: Vec<ButtonAction> = runner.run(&steps, 0).concat();
+
+        assert_eq!(
+            sent,
+            vec![
+                a.clone(),
+                b.clone(),
+                b.clone(),
+                b.clone(),
+                a,
+                b.clone(),
+                b.clone(),
+                b
+            ]
+        );
+        assert!(!runner.is_running());
+    }
+
+    #[test]
+    fn empty_repeats_are_skipped() {
+        let steps = [
+            MacroStep::Repeat {
+                count: 0,
+                steps: vec![MacroStep::Send(vec![down(MouseButton::Left)])],
+            },
+            MacroStep::Repeat {
+                count: 2,
+                steps: vec![],
+            },
+            MacroStep::Send(vec![down(MouseButton::Right)]),
+        ];
+        let mut runner = started();
+
+        assert_eq!(runner.run(&steps, 0), vec![vec![down(MouseButton::Right)]]);
+        assert!(!runner.is_running());
+    }
+
+    #[test]
+    fn long_macros_yield_and_are_due_right_away() {
+        let steps = [MacroStep::Repeat {
+            count: 100,
+            steps: vec![MacroStep::Send(vec![down(MouseButton::Left)])],
+        }];
+        let mut runner = started();
+
+        let first = runner.run(&steps, 7).len();
+
+        assert!(first > 0 && first <= MAX_STEPS_PER_RUN);
+        assert_eq!(runner.deadline(), Some(7));
+        assert!(runner.is_running());
+
+        let mut sent = first;
+        while runner.is_running() {
+            sent += runner.run(&steps, 8).len();
+        }
+
+        assert_eq!(sent, 100);
+    }
+}
@@ -0,0 +1,265 @@
+/// Columns are read as a bit mask
+pub const MAX_COLUMNS: usize = 32;
+
+/// Hardware side of a key matrix, one row is selected at a time
+pub trait MatrixIo {
+    fn select_row(&mut self, row: usize) -> anyhow::Result<()>;
+    /// Columns of the selected row with a key pressed, bit n is column n
+    fn read_columns(&mut self) -> anyhow::Result<u32>;
+    fn unselect_row(&mut self, row: usize) -> anyhow::Result<()>;
+}
+
+/// Rows that share two or more pressed columns with another row.
+///
+/// Without a diode per key, three pressed corners of a rectangle make the fourth one read
+/// as pressed too, so the state of those rows cannot be trusted until a key is released.
+pub fn ghosted_rows(raw: &[u32]) -> Vec<bool> {
+    let mut ghosted = vec![false; raw.len()];
+
+    for (row, columns) in raw.iter().enumerate() {
+        for (other, other_columns) in raw.iter().enumerate().skip(row + 1) {
+            if (columns & other_columns).count_ones() >= 2 {
+                ghosted[row] = true;
+                ghosted[other] = true;
+            }
+        }
+    }
+
+    ghosted
+}
+
+/// Finds the keys that changed between scans, keys are numbered `row * columns + column`.
+/// Debouncing is left to the consumer of the changes, like for the single pin buttons.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatrixScanner {
+    columns: usize,
+    state: Vec<u32>,
+}
+
+impl MatrixScanner {
+    pub fn new(rows: usize, columns: usize) -> Self {
+        assert!(
+            columns <= MAX_COLUMNS,
+            "a matrix has at most {} columns",
+            MAX_COLUMNS
+        );
+
+        MatrixScanner {
+            columns,
+            state: vec![0; rows],
+        }
+    }
+
+    pub fn keys(&self) -> usize {
+        self.state.len() * self.columns
+    }
+
+    pub fn is_pressed(&self, key: usize) -> bool {
+        let (row, column) = (key / self.columns, key % self.columns);
+
+        self.state
+            .get(row)
+            .is_some_and(|columns| columns & (1 << column) != 0)
+    }
+
+    pub fn scan<I: MatrixIo>(&mut self, io: &mut I) -> anyhow::Result<Vec<(usize, bool)>> {
+        let mask = if self.columns == MAX_COLUMNS {
+            u32::MAX
+        } else {
+            (1 << self.columns) - 1
+        };
+
+        let mut raw = Vec::with_capacity(self.state.len());
+
+        for row in 0..self.state.len() {
+            io.select_row(row)?;
+            let columns = io.read_columns();
+            io.unselect_row(row)?;
+
+            raw.push(columns? & mask);
+        }
+
+        Ok(self.update(&raw))
+    }
+
+    /// Takes a raw read of every row, returns the keys that changed as (key, pressed)
+    pub fn update(&mut self, raw: &[u32]) -> Vec<(usize, bool)> {
+        let ghosted = ghosted_rows(raw);
+        let mut changes = vec![];
+
+        for (row, columns) in raw.iter().enumerate().take(self.state.len()) {
+            // Ghosted rows keep their last trusted state
+            if ghosted[row] {
+                continue;
+            }
+
+            let changed = columns ^ self.state[row];
+
+            for column in (0..self.columns).filter(|column| changed & (1 << column) != 0) {
+                changes.push((row * self.columns + column, columns & (1 << column) != 0));
+            }
+
+            self.state[row] = *columns;
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, ensure};
+
+    use super::*;
+
+    /// Matrix without diodes: a selected row reads every column it reaches through pressed keys
+    #[derive(Default)]
+    struct FakeMatrix {
+        pressed: Vec<u32>,
+        // Bits the column pins read whatever is pressed, e.g. floating inputs past the last column
+        noise: u32,
+        selected: Option<usize>,
+    }
+
+    impl FakeMatrix {
+        fn new(rows: usize) -> Self {
+            FakeMatrix {
+                pressed: vec![0; rows],
+                ..Default::default()
+            }
+        }
+
+        fn set(&mut self, row: usize, column: usize, pressed: bool) {
+            if pressed {
+                self.pressed[row] |= 1 << column;
+            } else {
+                self.pressed[row] &= !(1 << column);
+            }
+        }
+    }
+
+    impl MatrixIo for FakeMatrix {
+        fn select_row(&mut self, row: usize) -> anyhow::Result<()> {
+            ensure!(self.selected.is_none(), "row {} selected twice", row);
+            self.selected = Some(row);
+            Ok(())
+        }
+
+        fn read_columns(&mut self) -> anyhow::Result<u32> {
+            let row = self.selected.ok_or_else(|| anyhow!("no row selected"))?;
+
+            let mut rows = 1u64 << row;
+            let mut columns = self.pressed[row];
+            loop {
+                let reached = self
+                    .pressed
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pressed)| *pressed & columns != 0)
+                    .fold(rows, |rows, (other, _)| rows | 1 << other);
+
+                if reached == rows {
+                    break;
+                }
+
+                rows = reached;
+                columns = self
+                    .pressed
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| rows & 1 << other != 0)
+                    .fold(columns, |columns, (_, pressed)| columns | pressed);
+            }
+
+            Ok(columns | self.noise)
+        }
+
+        fn unselect_row(&mut self, row: usize) -> anyhow::Result<()> {
+            ensure!(self.selected == Some(row), "row {} was not selected", row);
+            self.selected = None;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn single_key_press_and_release() {
+        let mut io = FakeMatrix::new(2);
+        let mut scanner = MatrixScanner::new(2, 3);
+
+        assert_eq!(scanner.keys(), 6);
+        assert!(scanner.scan(&mut io).unwrap().is_empty());
+
+        io.set(1, 2, true);
+        assert_eq!(scanner.scan(&mut io).unwrap(), vec![(5, true)]);
+        assert!(scanner.is_pressed(5));
+        assert!(scanner.scan(&mut io).unwrap().is_empty());
+
+        io.set(1, 2, false);
+        assert_eq!(scanner.scan(&mut io).unwrap(), vec![(5, false)]);
+        assert!(!scanner.is_pressed(5));
+    }
+
+    #[test]
+    fn several_keys_on_one_row() {
+        let mut io = FakeMatrix::new(2);
+        let mut scanner = MatrixScanner::new(2, 3);
+
+        io.set(0, 0, true);
+        io.set(0, 2, true);
+        assert_eq!(scanner.scan(&mut io).unwrap(), vec![(0, true), (2, true)]);
+
+        io.set(0, 0, false);
+        io.set(0, 1, true);
+        assert_eq!(scanner.scan(&mut io).unwrap(), vec![(0, false), (1, true)]);
+    }
+
+    #[test]
+    fn ghosted_rows_keep_their_state_until_a_release() {
+        let mut io = FakeMatrix::new(2);
+        let mut scanner = MatrixScanner::new(2, 2);
+
+        io.set(0, 0, true);
+        io.set(0, 1, true);
+        assert_eq!(scanner.scan(&mut io).unwrap(), vec![(0, true), (1, true)]);
+
+        // The third corner makes (1, 1) read as pressed, both rows are left as they were
+        io.set(1, 0, true);
+        assert!(scanner.scan(&mut io).unwrap().is_empty());
+        assert!(scanner.is_pressed(0) && scanner.is_pressed(1));
+        assert!(!scanner.is_pressed(2) && !scanner.is_pressed(3));
+
+        io.set(0, 1, false);
+        assert_eq!(scanner.scan(&mut io).unwrap(), vec![(1, false), (2, true)]);
+        assert!(!scanner.is_pressed(3));
+    }
+
+    #[test]
+    fn columns_past_the_matrix_are_masked() {
+        let mut io = FakeMatrix::new(1);
+        io.noise = !0b1111;
+        let mut scanner = MatrixScanner::new(1, 4);
+
+        assert!(scanner.scan(&mut io).unwrap().is_empty());
+
+        io.set(0, 3, true);
+        assert_eq!(scanner.scan(&mut io).unwrap(), vec![(3, true)]);
+    }
+
+    #[test]
+    fn last_column_of_the_widest_matrix() {
+        let mut io = FakeMatrix::new(1);
+        let mut scanner = MatrixScanner::new(1, MAX_COLUMNS);
+
+        io.set(0, MAX_COLUMNS - 1, true);
+        assert_eq!(
+            scanner.scan(&mut io).unwrap(),
+            vec![(MAX_COLUMNS - 1, true)]
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn more_columns_than_the_mask_holds() {
+        MatrixScanner::new(1, MAX_COLUMNS + 1);
+    }
+}
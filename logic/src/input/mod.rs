@@ -0,0 +1,10 @@
+pub mod combo;
+pub mod debounce;
+pub mod expander;
+pub mod gesture;
+pub mod layer;
+pub mod macros;
+pub mod matrix;
+pub mod mode;
+pub mod quadrature;
+pub mod validate;
@@ -0,0 +1,262 @@
+use std::time::Duration;
+
+use jojo_common::{
+    button::{ButtonAction, ButtonMode},
+    gamepad::GamepadButtonState,
+    mouse::MouseButtonState,
+};
+
+/// Latching button, each press flips the held state
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Toggle {
+    // Layer the actions were held down on, they are released on the same one
+    latched: Option<usize>,
+}
+
+impl Toggle {
+    /// Returns the new state, true when the actions are now held down on `layer`
+    pub fn press(&mut self, layer: usize) -> bool {
+        match self.latched.take() {
+            Some(_) => false,
+            None => {
+                self.latched = Some(layer);
+                true
+            }
+        }
+    }
+
+    /// Handles a debounced change, the new state on a press and None on a release,
+    /// which leaves the actions as they are
+    pub fn update(&mut self, pressed: bool, layer: usize) -> Option<bool> {
+        pressed.then(|| self.press(layer))
+    }
+
+    pub fn is_latched(&self) -> bool {
+        self.latched.is_some()
+    }
+
+    /// Layer of the held actions, None when the toggle is not latched
+    pub fn layer(&self) -> Option<usize> {
+        self.latched
+    }
+}
+
+/// Fires once on press and then every interval while the button is held,
+/// timestamps are microseconds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Turbo {
+    interval_us: u64,
+    next: Option<u64>,
+}
+
+impl Turbo {
+    pub fn new(interval: Duration) -> Self {
+        Turbo {
+            interval_us: interval.as_micros() as u64,
+            next: None,
+        }
+    }
+
+    pub fn press(&mut self, at: u64) {
+        self.next = Some(at + self.interval_us);
+    }
+
+    pub fn release(&mut self) {
+        self.next = None;
+    }
+
+    /// True when a shot is due, the next one is counted from now so a late poll does not burst
+    pub fn poll(&mut self, now: u64) -> bool {
+        match self.next {
+            Some(next) if now >= next => {
+                self.next = Some(now + self.interval_us);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn deadline(&self) -> Option<u64> {
+        self.next
+    }
+}
+
+/// A full press and release of every action, sent on each turbo shot
+pub fn click_actions(actions: &[ButtonAction]) -> Vec<ButtonAction> {
+    actions
+        .iter()
+        .flat_map(|action| match action {
+            ButtonAction::MouseButton(button, _) => vec![
+                ButtonAction::MouseButton(button.to_owned(), MouseButtonState::Down),
+                ButtonAction::MouseButton(button.to_owned(), MouseButtonState::Up),
+            ],
+            ButtonAction::GamepadButton(button, _) => vec![
+                ButtonAction::GamepadButton(button.to_owned(), GamepadButtonState::Pressed),
+                ButtonAction::GamepadButton(button.to_owned(), GamepadButtonState::Released),
+            ],
+            _ => vec![action.to_owned()],
+        })
+        .collect()
+}
+
+/// Actions sent when a button changes, `pressed` is the debounced state and not the pin level
+pub fn actions_for(
+    mode: &ButtonMode,
+    actions: &[ButtonAction],
+    pressed: bool,
+) -> Vec<ButtonAction> {
+    match mode {
+        ButtonMode::Hold => actions
+            .iter()
+            .map(|action| match action {
+                ButtonAction::MouseButton(button, _) => {
+                    let state = if pressed {
+                        MouseButtonState::Down
+                    } else {
+                        MouseButtonState::Up
+                    };
+
+                    ButtonAction::MouseButton(button.to_owned(), state)
+                }
+                ButtonAction::GamepadButton(button, _) => {
+                    let state = if pressed {
+                        GamepadButtonState::Pressed
+                    } else {
+                        GamepadButtonState::Released
+                    };
+
+                    ButtonAction::GamepadButton(button.to_owned(), state)
+                }
+                _ => action.to_owned(),
+            })
+            .collect(),
+        ButtonMode::Click if pressed => actions.to_vec(),
+        ButtonMode::Click => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jojo_common::{gamepad::GamepadButton, keyboard::KeyboardButton, mouse::MouseButton};
+
+    use super::*;
+
+    #[test]
+    fn toggle_keeps_the_layer_it_was_latched_on() {
+        let mut toggle = Toggle::default();
+        assert_eq!(toggle.layer(), None);
+
+        assert!(toggle.press(2));
+        assert!(toggle.is_latched());
+        assert_eq!(toggle.layer(), Some(2));
+
+        assert!(!toggle.press(0));
+        assert!(!toggle.is_latched());
+        assert_eq!(toggle.layer(), None);
+
+        assert!(toggle.press(1));
+        assert_eq!(toggle.layer(), Some(1));
+    }
+
+    #[test]
+    fn toggle_ignores_releases() {
+        let mut toggle = Toggle::default();
+
+        assert_eq!(toggle.update(true, 0), Some(true));
+        assert_eq!(toggle.update(false, 0), None);
+        assert!(toggle.is_latched());
+
+        // Released on the layer it was latched on even when pressed on another one
+        assert_eq!(toggle.update(true, 3), Some(false));
+        assert_eq!(toggle.update(false, 3), None);
+        assert!(!toggle.is_latched());
+    }
+
+    #[test]
+    fn turbo_fires_every_interval_while_held() {
+        let mut turbo = Turbo::new(Duration::from_millis(50));
+        assert_eq!(turbo.deadline(), None);
+        assert!(!turbo.poll(1_000_000));
+
+        turbo.press(0);
+        assert_eq!(turbo.deadline(), Some(50_000));
+        assert!(!turbo.poll(49_999));
+        assert!(turbo.poll(50_000));
+        assert_eq!(turbo.deadline(), Some(100_000));
+        assert!(!turbo.poll(50_000));
+
+        // Late poll, a single shot and the next one counted from now
+        assert!(turbo.poll(180_000));
+        assert!(!turbo.poll(229_999));
+        assert!(turbo.poll(230_000));
+
+        turbo.release();
+        assert_eq!(turbo.deadline(), None);
+        assert!(!turbo.poll(1_000_000));
+    }
+
+    #[test]
+    fn turbo_press_restarts_the_interval() {
+        let mut turbo = Turbo::new(Duration::from_millis(50));
+
+        turbo.press(0);
+        turbo.release();
+        turbo.press(30_000);
+        assert!(!turbo.poll(50_000));
+        assert!(turbo.poll(80_000));
+    }
+
+    #[test]
+    fn click_actions_press_and_release_every_button() {
+        let actions = [
+            ButtonAction::MouseButton(MouseButton::Left, MouseButtonState::Up),
+            ButtonAction::GamepadButton(GamepadButton::Button1, GamepadButtonState::Released),
+            ButtonAction::KeyboardButton(KeyboardButton::SequenceDsl("a".into())),
+        ];
+
+        assert_eq!(
+            click_actions(&actions),
+            [
+                ButtonAction::MouseButton(MouseButton::Left, MouseButtonState::Down),
+                ButtonAction::MouseButton(MouseButton::Left, MouseButtonState::Up),
+                ButtonAction::GamepadButton(GamepadButton::Button1, GamepadButtonState::Pressed),
+                ButtonAction::GamepadButton(GamepadButton::Button1, GamepadButtonState::Released),
+                ButtonAction::KeyboardButton(KeyboardButton::SequenceDsl("a".into())),
+            ]
+        );
+    }
+
+    #[test]
+    fn hold_follows_the_button() {
+        let actions = [
+            ButtonAction::MouseButton(MouseButton::Right, MouseButtonState::Up),
+            ButtonAction::GamepadButton(GamepadButton::Button2, GamepadButtonState::Released),
+        ];
+
+        assert_eq!(
+            actions_for(&ButtonMode::Hold, &actions, true),
+            [
+                ButtonAction::MouseButton(MouseButton::Right, MouseButtonState::Down),
+                ButtonAction::GamepadButton(GamepadButton::Button2, GamepadButtonState::Pressed),
+            ]
+        );
+        assert_eq!(
+            actions_for(&ButtonMode::Hold, &actions, false),
+            [
+                ButtonAction::MouseButton(MouseButton::Right, MouseButtonState::Up),
+                ButtonAction::GamepadButton(GamepadButton::Button2, GamepadButtonState::Released),
+            ]
+        );
+    }
+
+    #[test]
+    fn click_sends_the_actions_on_press_only() {
+        let actions = [ButtonAction::MouseButton(
+            MouseButton::Middle,
+            MouseButtonState::Down,
+        )];
+
+        assert_eq!(actions_for(&ButtonMode::Click, &actions, true), actions);
+        assert!(actions_for(&ButtonMode::Click, &actions, false).is_empty());
+    }
+}
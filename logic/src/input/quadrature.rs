@@ -0,0 +1,228 @@
+// Count of each transition of the A and B levels, indexed by previous << 2 | current.
+// Clockwise is 00 -> 01 -> 11 -> 10, jumps over a state are bounces or missed edges and count 0
+const TRANSITIONS: [i8; 16] = [0, 1, -1, 0, -1, 0, 0, 1, 1, 0, 0, -1, 0, -1, 1, 0];
+
+/// Decodes the two channels of a rotary encoder into counts, 4 per full quadrature cycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuadratureDecoder {
+    state: u8,
+}
+
+impl QuadratureDecoder {
+    pub fn new(a: bool, b: bool) -> Self {
+        QuadratureDecoder {
+            state: levels(a, b),
+        }
+    }
+
+    /// Takes the current levels, returns 1 clockwise, -1 counter clockwise, 0 otherwise
+    pub fn update(&mut self, a: bool, b: bool) -> i8 {
+        let current = levels(a, b);
+        let count = TRANSITIONS[usize::from(self.state << 2 | current)];
+        self.state = current;
+
+        count
+    }
+}
+
+fn levels(a: bool, b: bool) -> u8 {
+    u8::from(a) << 1 | u8::from(b)
+}
+
+/// Difference between two reads of a counter that goes back to 0 when it reaches `±limit`
+pub fn wrap_delta(previous: i16, current: i16, limit: i16) -> i32 {
+    let limit = i32::from(limit);
+    let delta = i32::from(current) - i32::from(previous);
+
+    if delta > limit / 2 {
+        delta - limit
+    } else if delta < -limit / 2 {
+        delta + limit
+    } else {
+        delta
+    }
+}
+
+/// Steps turned faster than `fast_us` apart are multiplied, up to `max_multiplier`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Acceleration {
+    pub fast_us: u64,
+    pub max_multiplier: u8,
+}
+
+/// Turns counts into steps, timestamps are microseconds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepCounter {
+    counts_per_step: i32,
+    residual: i32,
+    acceleration: Option<Acceleration>,
+    // Time and direction of the last step
+    last: Option<(u64, i32)>,
+}
+
+impl StepCounter {
+    pub fn new(
+        counts_per_detent: u8,
+        detents_per_step: u8,
+        acceleration: Option<Acceleration>,
+    ) -> Self {
+        let counts_per_step =
+            i32::from(counts_per_detent.max(1)) * i32::from(detents_per_step.max(1));
+
+        StepCounter {
+            counts_per_step,
+            residual: 0,
+            acceleration,
+            last: None,
+        }
+    }
+
+    /// Adds the counts turned since the last call, returns the steps to send, negative counter clockwise
+    pub fn turn(&mut self, counts: i32, at: u64) -> i32 {
+        self.residual += counts;
+
+        let steps = self.residual / self.counts_per_step;
+        if steps == 0 {
+            return 0;
+        }
+        self.residual -= steps * self.counts_per_step;
+
+        let direction = steps.signum();
+        let multiplier = match (self.acceleration, self.last) {
+            // A change of direction starts slow again
+            (Some(acceleration), Some((last, last_direction))) if last_direction == direction => {
+                let interval = at.saturating_sub(last) / u64::from(steps.unsigned_abs());
+                let multiplier = acceleration.fast_us / interval.max(1);
+
+                multiplier.clamp(1, u64::from(acceleration.max_multiplier.max(1))) as i32
+            }
+            _ => 1,
+        };
+
+        self.last = Some((at, direction));
+
+        steps * multiplier
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Levels of a clockwise cycle, from and back to rest
+    const CLOCKWISE: [(bool, bool); 4] =
+        [(false, true), (true, true), (true, false), (false, false)];
+
+    fn turn(
+        decoder: &mut QuadratureDecoder,
+        levels: impl IntoIterator<Item = (bool, bool)>,
+    ) -> i32 {
+        levels
+            .into_iter()
+            .map(|(a, b)| i32::from(decoder.update(a, b)))
+            .sum()
+    }
+
+    #[test]
+    fn full_cycles_count_four() {
+        let mut decoder = QuadratureDecoder::new(false, false);
+
+        assert_eq!(turn(&mut decoder, CLOCKWISE), 4);
+
+        let counter_clockwise = CLOCKWISE
+            .iter()
+            .rev()
+            .skip(1)
+            .copied()
+            .chain([(false, false)]);
+        assert_eq!(turn(&mut decoder, counter_clockwise), -4);
+    }
+
+    #[test]
+    fn unchanged_and_skipped_states_count_zero() {
+        let mut decoder = QuadratureDecoder::new(false, false);
+
+        assert_eq!(decoder.update(false, false), 0);
+        // 00 -> 11 jumps over a state, the direction is unknown
+        assert_eq!(decoder.update(true, true), 0);
+        assert_eq!(decoder.update(false, false), 0);
+
+        assert_eq!(decoder.update(false, true), 1);
+        assert_eq!(decoder.update(true, false), 0);
+        // Decoding goes on from the state it jumped to
+        assert_eq!(decoder.update(false, false), 1);
+    }
+
+    #[test]
+    fn wrap_delta_across_the_limit() {
+        // PCNT_LIMIT of the encoder task
+        let limit = 10_000;
+
+        assert_eq!(wrap_delta(0, 25, limit), 25);
+        assert_eq!(wrap_delta(25, 0, limit), -25);
+        // Went up past the limit and back to 0
+        assert_eq!(wrap_delta(9_990, 5, limit), 15);
+        // Went down past -limit and back to 0
+        assert_eq!(wrap_delta(-9_990, -5, limit), -15);
+        assert_eq!(wrap_delta(5, 9_990, limit), -15);
+    }
+
+    #[test]
+    fn steps_keep_the_residual_counts() {
+        let mut steps = StepCounter::new(4, 1, None);
+
+        assert_eq!(steps.turn(3, 0), 0);
+        assert_eq!(steps.turn(3, 1), 1);
+        assert_eq!(steps.turn(2, 2), 1);
+        assert_eq!(steps.turn(-5, 3), -1);
+        assert_eq!(steps.turn(-3, 4), -1);
+        assert_eq!(steps.turn(9, 5), 2);
+    }
+
+    #[test]
+    fn detents_per_step_divide_the_steps() {
+        let mut steps = StepCounter::new(4, 3, None);
+
+        assert_eq!(steps.turn(8, 0), 0);
+        assert_eq!(steps.turn(4, 1), 1);
+        assert_eq!(steps.turn(24, 2), 2);
+
+        // Zeros are taken as one
+        let mut steps = StepCounter::new(0, 0, None);
+        assert_eq!(steps.turn(2, 0), 2);
+    }
+
+    #[test]
+    fn fast_steps_are_multiplied_up_to_the_clamp() {
+        let acceleration = Acceleration {
+            fast_us: 30_000,
+            max_multiplier: 4,
+        };
+        let mut steps = StepCounter::new(4, 1, Some(acceleration));
+
+        // The first step has nothing to be compared with
+        assert_eq!(steps.turn(4, 0), 1);
+        // Slower than fast_us
+        assert_eq!(steps.turn(4, 50_000), 1);
+        // 15ms apart, twice as fast
+        assert_eq!(steps.turn(4, 65_000), 2);
+        // 1ms apart would be 30 times, clamped
+        assert_eq!(steps.turn(4, 66_000), 4);
+        // Two steps in 10ms, 5ms each
+        assert_eq!(steps.turn(8, 76_000), 8);
+    }
+
+    #[test]
+    fn a_change_of_direction_starts_slow() {
+        let acceleration = Acceleration {
+            fast_us: 30_000,
+            max_multiplier: 4,
+        };
+        let mut steps = StepCounter::new(4, 1, Some(acceleration));
+
+        assert_eq!(steps.turn(4, 0), 1);
+        assert_eq!(steps.turn(4, 1_000), 4);
+        assert_eq!(steps.turn(-4, 2_000), -1);
+        assert_eq!(steps.turn(-4, 3_000), -4);
+    }
+}
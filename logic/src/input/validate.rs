@@ -0,0 +1,157 @@
+use std::fmt;
+
+use uuid::Uuid;
+
+use super::layer::LayerKey;
+
+// Biggest input configuration accepted in flash
+pub const INPUT_FLASH_LIMIT: usize = 8192;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputError {
+    TooLarge(usize),
+    /// Index of the combo in the list
+    ComboTooShort(usize),
+    UnknownComboButton(usize, Uuid),
+    /// Layer number, from 1 on
+    UnknownLayerButton(usize, Uuid),
+    LayerOutOfRange(Uuid, u8),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::TooLarge(size) => write!(
+                f,
+                "takes {} bytes, the limit is {}",
+                size, INPUT_FLASH_LIMIT
+            ),
+            InputError::ComboTooShort(index) => {
+                write!(f, "combo {} needs two different buttons", index)
+            }
+            InputError::UnknownComboButton(index, id) => {
+                write!(f, "combo {} references an unknown button {}", index, id)
+            }
+            InputError::UnknownLayerButton(layer, id) => {
+                write!(f, "layer {} references an unknown button {}", layer, id)
+            }
+            InputError::LayerOutOfRange(id, layer) => {
+                write!(
+                    f,
+                    "button {} switches to layer {} that does not exist",
+                    id, layer
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// Checks a combo can be detected, `known` are the buttons of the device when there is one
+pub fn validate_combo(
+    index: usize,
+    buttons: &[Uuid],
+    known: Option<&[Uuid]>,
+) -> Result<(), InputError> {
+    if let Some(id) = known.and_then(|known| buttons.iter().find(|id| !known.contains(id))) {
+        return Err(InputError::UnknownComboButton(index, *id));
+    }
+
+    let mut distinct = buttons.to_vec();
+    distinct.sort_unstable();
+    distinct.dedup();
+
+    if distinct.len() < 2 {
+        return Err(InputError::ComboTooShort(index));
+    }
+
+    Ok(())
+}
+
+/// Checks the buttons a layer gives actions to, `layer` counts from 1
+pub fn validate_layer(
+    layer: usize,
+    buttons: &[Uuid],
+    known: Option<&[Uuid]>,
+) -> Result<(), InputError> {
+    match known.and_then(|known| buttons.iter().find(|id| !known.contains(id))) {
+        Some(id) => Err(InputError::UnknownLayerButton(layer, *id)),
+        None => Ok(()),
+    }
+}
+
+/// Checks the key switches to a layer that exists, `layer_count` includes layer 0
+pub fn validate_layer_key(id: Uuid, key: LayerKey, layer_count: usize) -> Result<(), InputError> {
+    let layer = match key {
+        LayerKey::Momentary(layer) | LayerKey::Toggle(layer) => layer,
+    };
+
+    if usize::from(layer) >= layer_count {
+        return Err(InputError::LayerOutOfRange(id, layer));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids() -> [Uuid; 3] {
+        [Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3)]
+    }
+
+    #[test]
+    fn combo_needs_two_different_buttons() {
+        let [a, b, _] = ids();
+
+        assert_eq!(validate_combo(0, &[a, b], None), Ok(()));
+        assert_eq!(
+            validate_combo(1, &[a, a], None),
+            Err(InputError::ComboTooShort(1))
+        );
+        assert_eq!(
+            validate_combo(2, &[], None),
+            Err(InputError::ComboTooShort(2))
+        );
+    }
+
+    #[test]
+    fn combo_buttons_must_be_on_the_device() {
+        let [a, b, c] = ids();
+
+        assert_eq!(validate_combo(0, &[a, b], Some(&[a, b])), Ok(()));
+        assert_eq!(
+            validate_combo(0, &[a, c], Some(&[a, b])),
+            Err(InputError::UnknownComboButton(0, c))
+        );
+    }
+
+    #[test]
+    fn layer_buttons_must_be_on_the_device() {
+        let [a, b, c] = ids();
+
+        assert_eq!(validate_layer(1, &[a, c], None), Ok(()));
+        assert_eq!(
+            validate_layer(1, &[a, c], Some(&[a, b])),
+            Err(InputError::UnknownLayerButton(1, c))
+        );
+    }
+
+    #[test]
+    fn layer_key_in_range() {
+        let [a, _, _] = ids();
+
+        assert_eq!(validate_layer_key(a, LayerKey::Momentary(0), 1), Ok(()));
+        assert_eq!(validate_layer_key(a, LayerKey::Toggle(2), 3), Ok(()));
+        assert_eq!(
+            validate_layer_key(a, LayerKey::Toggle(3), 3),
+            Err(InputError::LayerOutOfRange(a, 3))
+        );
+        assert_eq!(
+            validate_layer_key(a, LayerKey::Momentary(1), 1),
+            Err(InputError::LayerOutOfRange(a, 1))
+        );
+    }
+}
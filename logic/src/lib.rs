@@ -0,0 +1,10 @@
+pub mod bundle;
+pub mod console;
+pub mod device;
+pub mod dns;
+pub mod http;
+pub mod input;
+pub mod network;
+pub mod scan;
+pub mod settings;
+pub mod store;
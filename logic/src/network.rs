@@ -0,0 +1,91 @@
+use std::fmt;
+
+use jojo_common::network::NetworkCredentials;
+
+// Limits of the heapless strings the wifi driver configuration uses
+pub const SSID_MAX_LEN: usize = 32;
+pub const PASSWORD_MAX_LEN: usize = 64;
+// WPA2 personal requires at least 8 characters, an empty password means an open network
+pub const PASSWORD_MIN_LEN: usize = 8;
+// Printed instead of secrets in the logs
+pub const REDACTED: &str = "<redacted>";
+
+/// Debug view of the credentials without the password, jojo_common derives a Debug that prints it
+pub struct Redacted<'a>(pub &'a NetworkCredentials);
+
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetworkCredentials")
+            .field("ssid", &self.0.ssid.to_string())
+            .field("password", &REDACTED)
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialsError {
+    EmptySsid,
+    SsidTooLong,
+    PasswordTooShort,
+    PasswordTooLong,
+}
+
+impl CredentialsError {
+    pub fn field(&self) -> &'static str {
+        match self {
+            CredentialsError::EmptySsid | CredentialsError::SsidTooLong => "ssid",
+            CredentialsError::PasswordTooShort | CredentialsError::PasswordTooLong => "password",
+        }
+    }
+}
+
+impl fmt::Display for CredentialsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialsError::EmptySsid => write!(f, "ssid cannot be empty"),
+            CredentialsError::SsidTooLong => {
+                write!(f, "ssid cannot be longer than {} bytes", SSID_MAX_LEN)
+            }
+            CredentialsError::PasswordTooShort => write!(
+                f,
+                "password must have at least {} characters or be empty",
+                PASSWORD_MIN_LEN
+            ),
+            CredentialsError::PasswordTooLong => {
+                write!(
+                    f,
+                    "password cannot be longer than {} bytes",
+                    PASSWORD_MAX_LEN
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for CredentialsError {}
+
+pub fn validate_credentials(ssid: &str, password: &str) -> Result<(), CredentialsError> {
+    if ssid.is_empty() {
+        return Err(CredentialsError::EmptySsid);
+    }
+    if ssid.len() > SSID_MAX_LEN {
+        return Err(CredentialsError::SsidTooLong);
+    }
+    if !password.is_empty() && password.len() < PASSWORD_MIN_LEN {
+        return Err(CredentialsError::PasswordTooShort);
+    }
+    if password.len() > PASSWORD_MAX_LEN {
+        return Err(CredentialsError::PasswordTooLong);
+    }
+
+    Ok(())
+}
+
+/// Builds credentials through serde, jojo_common does not expose a constructor
+pub fn credentials(ssid: &str, password: &str) -> anyhow::Result<NetworkCredentials> {
+    validate_credentials(ssid, password)?;
+
+    Ok(serde_json::from_value(
+        serde_json::json!({ "ssid": ssid, "password": password }),
+    )?)
+}
@@ -0,0 +1,180 @@
+use std::{cmp::Reverse, ops::RangeInclusive, time::Duration};
+
+use serde::Serialize;
+
+// Channels of the 2.4 GHz band, 14 is only allowed in Japan
+pub const MAX_CHANNEL: u8 = 14;
+// Weakest signal a filter accepts, the radio does not report anything lower
+pub const MIN_RSSI: i8 = -127;
+// Fixed part of a scan, the driver has to switch the radio to the station side
+const SCAN_BASE_TIMEOUT: Duration = Duration::from_secs(2);
+// A blocking scan dwells up to 360 ms on a passive channel, the rest is slack for the driver
+const SCAN_CHANNEL_TIMEOUT: Duration = Duration::from_millis(750);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanFilter {
+    pub min_rssi: i8,
+    pub channels: RangeInclusive<u8>,
+}
+
+impl Default for ScanFilter {
+    fn default() -> Self {
+        ScanFilter::new(-90, 1..=13)
+    }
+}
+
+impl ScanFilter {
+    pub fn new(min_rssi: i8, channels: RangeInclusive<u8>) -> Self {
+        ScanFilter { min_rssi, channels }
+    }
+
+    /// Reads `min_rssi`, `channel_from` and `channel_to` from a query string, missing values keep the default
+    pub fn from_query(query: &str) -> anyhow::Result<Self> {
+        let mut filter = ScanFilter::default();
+        let (mut from, mut to) = (*filter.channels.start(), *filter.channels.end());
+
+        for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+            match key.as_ref() {
+                "min_rssi" => filter.min_rssi = value.parse()?,
+                "channel_from" => from = value.parse()?,
+                "channel_to" => to = value.parse()?,
+                _ => {}
+            }
+        }
+
+        if !(MIN_RSSI..=0).contains(&filter.min_rssi) {
+            anyhow::bail!("invalid minimum rssi {}", filter.min_rssi);
+        }
+
+        if from == 0 || to > MAX_CHANNEL || from > to {
+            anyhow::bail!("invalid channel range {}..={}", from, to);
+        }
+
+        filter.channels = from..=to;
+
+        Ok(filter)
+    }
+
+    /// How long a scan of the filtered channels may take, one channel is scanned at a time
+    pub fn timeout(&self) -> Duration {
+        SCAN_BASE_TIMEOUT + SCAN_CHANNEL_TIMEOUT * self.channels.len() as u32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ScanEntry {
+    pub ssid: String,
+    pub bssid: String,
+    pub rssi: i8,
+    pub channel: u8,
+    pub auth_method: Option<String>,
+}
+
+/// Keeps the strongest entry of each SSID, drops hidden networks and sorts by signal
+pub fn dedup_networks(networks: Vec<ScanEntry>) -> Vec<ScanEntry> {
+    let mut unique: Vec<ScanEntry> = Vec::with_capacity(networks.len());
+
+    for network in networks.into_iter().filter(|n| !n.ssid.is_empty()) {
+        match unique.iter_mut().find(|u| u.ssid == network.ssid) {
+            Some(existing) if existing.rssi < network.rssi => *existing = network,
+            Some(_) => {}
+            None => unique.push(network),
+        }
+    }
+
+    unique.sort_by_key(|network| Reverse(network.rssi));
+
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ssid: &str, bssid: &str, rssi: i8) -> ScanEntry {
+        ScanEntry {
+            ssid: ssid.to_string(),
+            bssid: bssid.to_string(),
+            rssi,
+            channel: 6,
+            auth_method: None,
+        }
+    }
+
+    #[test]
+    fn an_empty_query_keeps_the_defaults() {
+        assert_eq!(ScanFilter::from_query("").unwrap(), ScanFilter::default());
+        assert_eq!(
+            ScanFilter::from_query("other=1").unwrap(),
+            ScanFilter::new(-90, 1..=13)
+        );
+    }
+
+    #[test]
+    fn the_query_overrides_each_value() {
+        let filter = ScanFilter::from_query("min_rssi=-70&channel_from=6&channel_to=11").unwrap();
+
+        assert_eq!(filter, ScanFilter::new(-70, 6..=11));
+        assert_eq!(
+            ScanFilter::from_query("channel_to=14").unwrap().channels,
+            1..=14
+        );
+    }
+
+    #[test]
+    fn out_of_range_channels_are_rejected() {
+        assert!(ScanFilter::from_query("channel_from=0").is_err());
+        assert!(ScanFilter::from_query("channel_to=15").is_err());
+        assert!(ScanFilter::from_query("channel_from=9&channel_to=3").is_err());
+        assert!(ScanFilter::from_query("channel_from=300").is_err());
+        assert!(ScanFilter::from_query("channel_from=a").is_err());
+    }
+
+    #[test]
+    fn out_of_range_rssi_is_rejected() {
+        assert!(ScanFilter::from_query("min_rssi=1").is_err());
+        assert!(ScanFilter::from_query("min_rssi=-200").is_err());
+        assert!(ScanFilter::from_query("min_rssi=strong").is_err());
+        assert_eq!(
+            ScanFilter::from_query("min_rssi=-127").unwrap().min_rssi,
+            MIN_RSSI
+        );
+    }
+
+    #[test]
+    fn the_timeout_grows_with_the_channels() {
+        let one = ScanFilter::new(-90, 6..=6).timeout();
+        let all = ScanFilter::new(-90, 1..=14).timeout();
+
+        assert!(one < all);
+        assert_eq!(all - one, SCAN_CHANNEL_TIMEOUT * 13);
+    }
+
+    #[test]
+    fn dedup_keeps_the_strongest_bssid() {
+        let networks = vec![
+            entry("home", "aa", -80),
+            entry("home", "bb", -40),
+            entry("home", "cc", -60),
+        ];
+
+        assert_eq!(dedup_networks(networks), vec![entry("home", "bb", -40)]);
+    }
+
+    #[test]
+    fn dedup_drops_hidden_networks_and_sorts_by_signal() {
+        let networks = vec![
+            entry("office", "aa", -70),
+            entry("", "bb", -20),
+            entry("home", "cc", -50),
+            entry("cafe", "dd", -85),
+        ];
+
+        let ssids: Vec<String> = dedup_networks(networks)
+            .into_iter()
+            .map(|n| n.ssid)
+            .collect();
+
+        assert_eq!(ssids, ["home", "office", "cafe"]);
+    }
+}
@@ -0,0 +1,42 @@
+use std::{fmt, net::SocketAddr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingsError {
+    EmptyWebsocketPath,
+    InvalidAddress(&'static str, String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::EmptyWebsocketPath => write!(f, "websocket_path cannot be empty"),
+            SettingsError::InvalidAddress(field, value) => {
+                write!(f, "{} is not a valid ip:port address: {:?}", field, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+/// Checks the server settings can be used to connect
+pub fn validate(
+    websocket_path: &str,
+    broadcast_address: &str,
+    broadcast_bind_address: &str,
+) -> Result<(), SettingsError> {
+    if websocket_path.trim_matches('/').is_empty() {
+        return Err(SettingsError::EmptyWebsocketPath);
+    }
+
+    for (field, value) in [
+        ("broadcast_address", broadcast_address),
+        ("broadcast_bind_address", broadcast_bind_address),
+    ] {
+        if value.parse::<SocketAddr>().is_err() {
+            return Err(SettingsError::InvalidAddress(field, value.to_string()));
+        }
+    }
+
+    Ok(())
+}
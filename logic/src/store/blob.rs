@@ -0,0 +1,112 @@
+use anyhow::bail;
+
+use super::ALL_TAGS;
+
+// Stored under the tag instead of the value when it had to be split, followed by the number of
+// chunks and the total length. Headers of the store and legacy bincode never start like this.
+const MANIFEST_MAGIC: [u8; 2] = [0xa5, 0x43];
+const MANIFEST_LEN: usize = MANIFEST_MAGIC.len() + 2 + 4;
+// Blobs bigger than a page are spread by NVS across pages, keeping them under this size
+// avoids depending on finding that many free pages at once
+pub const CHUNK_SIZE: usize = 1984;
+// Chunk keys are `<key>#<index>` with a single base 36 digit, so the longest tag with its slot
+// suffix stays within the 15 characters limit
+pub const MAX_CHUNKS: usize = 36;
+// Longest key NVS accepts
+pub const NVS_KEY_MAX_LEN: usize = 15;
+// `.a` of the slot and `#0` of the chunk
+const KEY_SUFFIX_LEN: usize = 4;
+
+// Every tag must leave room for its slot and chunk suffixes, checked when building
+const _: () = {
+    let mut index = 0;
+    while index < ALL_TAGS.len() {
+        assert!(ALL_TAGS[index].len() + KEY_SUFFIX_LEN <= NVS_KEY_MAX_LEN);
+        index += 1;
+    }
+};
+
+pub fn chunk_key(key: &str, index: usize) -> anyhow::Result<String> {
+    let Some(digit) = u32::try_from(index)
+        .ok()
+        .and_then(|index| char::from_digit(index, 36))
+    else {
+        bail!(
+            "chunk {} of {} is over the {} chunks limit",
+            index,
+            key,
+            MAX_CHUNKS
+        );
+    };
+
+    Ok(format!("{}#{}", key, digit))
+}
+
+pub fn encode_manifest(chunks: usize, len: usize) -> Vec<u8> {
+    let mut manifest = Vec::with_capacity(MANIFEST_LEN);
+    manifest.extend_from_slice(&MANIFEST_MAGIC);
+    manifest.extend_from_slice(&(chunks as u16).to_le_bytes());
+    manifest.extend_from_slice(&(len as u32).to_le_bytes());
+
+    manifest
+}
+
+/// Returns the number of chunks and the total length when `raw` is a manifest
+pub fn decode_manifest(raw: &[u8]) -> Option<(usize, usize)> {
+    match raw {
+        [m0, m1, c0, c1, l0, l1, l2, l3] if [*m0, *m1] == MANIFEST_MAGIC => Some((
+            u16::from_le_bytes([*c0, *c1]) as usize,
+            u32::from_le_bytes([*l0, *l1, *l2, *l3]) as usize,
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::slot;
+
+    #[test]
+    fn every_tag_fits_with_its_suffixes() {
+        for tag in ALL_TAGS {
+            let key = chunk_key(&slot::slot_key(tag, 'a'), MAX_CHUNKS - 1).unwrap();
+
+            assert!(key.len() <= NVS_KEY_MAX_LEN, "{} is too long", key);
+        }
+    }
+
+    #[test]
+    fn chunk_keys_use_one_base_36_digit() {
+        assert_eq!(chunk_key("device.a", 0).unwrap(), "device.a#0");
+        assert_eq!(chunk_key("device.a", 10).unwrap(), "device.a#a");
+        assert_eq!(chunk_key("device.a", 35).unwrap(), "device.a#z");
+    }
+
+    #[test]
+    fn chunk_indexes_over_the_limit_are_errors() {
+        assert!(chunk_key("device.a", MAX_CHUNKS).is_err());
+        assert!(chunk_key("device.a", usize::MAX).is_err());
+    }
+
+    #[test]
+    fn manifests_round_trip() {
+        let manifest = encode_manifest(3, 5000);
+
+        assert_eq!(manifest.len(), MANIFEST_LEN);
+        assert_eq!(decode_manifest(&manifest), Some((3, 5000)));
+    }
+
+    #[test]
+    fn truncated_manifests_are_values() {
+        let manifest = encode_manifest(3, 5000);
+
+        for len in 0..MANIFEST_LEN {
+            assert_eq!(decode_manifest(&manifest[..len]), None);
+        }
+
+        let mut longer = manifest.clone();
+        longer.push(0);
+        assert_eq!(decode_manifest(&longer), None);
+    }
+}
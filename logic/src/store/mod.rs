@@ -0,0 +1,531 @@
+use std::fmt;
+
+use jojo_common::{device::Device, network::NetworkCredentials};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::device::DEVICE_FLASH_LIMIT;
+
+pub mod blob;
+pub mod slot;
+
+use slot::SLOTS;
+
+// NVS keys are 15 characters at most, blob checks at compile time that each tag leaves room for
+// its slot and chunk suffixes
+pub const NETWORK_TAG: &str = "client_cred";
+pub const DEVICE_TAG: &str = "device";
+pub const AP_PASSWORD_TAG: &str = "ap_password";
+pub const SERVER_TAG: &str = "server";
+pub const OTP_TOKEN_TAG: &str = "otp_token";
+pub const CALIBRATION_TAG: &str = "calibration";
+// Store key of older firmwares, kept in flash next to the secrets until they move to the eFuse key
+pub const DEVICE_KEY_TAG: &str = "device_key";
+pub const INPUT_TAG: &str = "input";
+// Every key stored in the namespace, used to erase the whole configuration
+pub const ALL_TAGS: [&str; 8] = [
+    NETWORK_TAG,
+    DEVICE_TAG,
+    AP_PASSWORD_TAG,
+    SERVER_TAG,
+    OTP_TOKEN_TAG,
+    CALIBRATION_TAG,
+    DEVICE_KEY_TAG,
+    INPUT_TAG,
+];
+
+// Written before the version so raw bincode from older firmwares is never taken for a header,
+// their first two bytes are the low bytes of a length and cannot be 0x4aa5
+const MAGIC: [u8; 2] = [0xa5, 0x4a];
+pub const HEADER_LEN: usize = MAGIC.len() + 2;
+// Version of the values written without a header, before this store existed
+pub const LEGACY_VERSION: u16 = 0;
+
+/// Turns a payload of version n into a payload of version n + 1
+pub type Migration = fn(Vec<u8>) -> anyhow::Result<Vec<u8>>;
+
+/// A value stored in flash under its own tag, as a versioned header plus bincode.
+/// When the layout of a type changes bump VERSION and push the migration from the previous one.
+pub trait Schema: Serialize + DeserializeOwned {
+    const TAG: &'static str;
+    const VERSION: u16;
+    /// Biggest encoded value, header included, refused on save
+    const MAX_SIZE: usize;
+    /// Values holding secrets are encrypted with the device key before reaching flash
+    const ENCRYPTED: bool = false;
+
+    /// migrations()[n] upgrades a version n payload, there must be exactly VERSION of them
+    fn migrations() -> &'static [Migration];
+}
+
+/// Version 0 was the same bincode without header
+pub fn from_legacy(payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    Ok(payload)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    NewerVersion(u16),
+    MissingMigration(u16),
+    Migration(u16, String),
+    Corrupted(String),
+    TooLarge(usize, usize),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::NewerVersion(version) => {
+                write!(f, "written by a newer firmware with version {}", version)
+            }
+            StoreError::MissingMigration(version) => {
+                write!(f, "no migration from version {}", version)
+            }
+            StoreError::Migration(version, err) => {
+                write!(f, "migration from version {} failed: {}", version, err)
+            }
+            StoreError::Corrupted(err) => write!(f, "cannot decode value: {}", err),
+            StoreError::TooLarge(size, limit) => {
+                write!(f, "value takes {} bytes, the limit is {}", size, limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+/// Splits a stored value in its version and payload
+pub fn split_header(raw: &[u8]) -> (u16, &[u8]) {
+    match raw {
+        [m0, m1, v0, v1, payload @ ..] if [*m0, *m1] == MAGIC => {
+            (u16::from_le_bytes([*v0, *v1]), payload)
+        }
+        _ => (LEGACY_VERSION, raw),
+    }
+}
+
+pub fn encoded_len<T: Schema>(value: &T) -> Result<usize, StoreError> {
+    let size =
+        bincode::serialized_size(value).map_err(|err| StoreError::Corrupted(err.to_string()))?;
+
+    Ok(HEADER_LEN + size as usize)
+}
+
+pub fn encode<T: Schema>(value: &T) -> Result<Vec<u8>, StoreError> {
+    let payload =
+        bincode::serialize(value).map_err(|err| StoreError::Corrupted(err.to_string()))?;
+
+    let size = HEADER_LEN + payload.len();
+    if size > T::MAX_SIZE {
+        return Err(StoreError::TooLarge(size, T::MAX_SIZE));
+    }
+
+    let mut raw = Vec::with_capacity(size);
+    raw.extend_from_slice(&MAGIC);
+    raw.extend_from_slice(&T::VERSION.to_le_bytes());
+    raw.extend_from_slice(&payload);
+
+    Ok(raw)
+}
+
+/// Decodes a stored value, running the migrations when it was written with an older version
+pub fn decode<T: Schema>(raw: &[u8]) -> Result<T, StoreError> {
+    let (version, payload) = split_header(raw);
+
+    if version > T::VERSION {
+        return Err(StoreError::NewerVersion(version));
+    }
+
+    let mut payload = payload.to_vec();
+    for from in version..T::VERSION {
+        let migration = T::migrations()
+            .get(from as usize)
+            .ok_or(StoreError::MissingMigration(from))?;
+
+        payload = migration(payload).map_err(|err| StoreError::Migration(from, err.to_string()))?;
+    }
+
+    bincode::deserialize(&payload).map_err(|err| StoreError::Corrupted(err.to_string()))
+}
+
+/// Decodes the record of a slot, `open` gets the stored value back in clear (decrypting it when
+/// needed) and its error is reported as is
+pub fn decode_slot<T: Schema>(
+    raw: &[u8],
+    open: impl FnOnce(&[u8]) -> Result<Vec<u8>, String>,
+) -> Result<(u32, T), String> {
+    let record = slot::decode_record(raw).map_err(|err| err.to_string())?;
+    let plaintext = open(record.value)?;
+
+    decode(&plaintext)
+        .map(|value| (record.sequence, value))
+        .map_err(|err| err.to_string())
+}
+
+/// A stored copy that could not be read, kept until the websocket task reports it to the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CorruptionEvent {
+    pub tag: &'static str,
+    // None for values written before the A/B slots
+    pub slot: Option<char>,
+    pub detail: String,
+    // Whether the other slot had a good copy that was used instead
+    pub recovered: bool,
+}
+
+// Events waiting for the server, more than this are only logged
+const MAX_CORRUPTION_EVENTS: usize = 16;
+
+pub struct CorruptionLog {
+    // Copies already queued once since boot, a corrupted slot stays so until its next save
+    reported: Vec<(&'static str, Option<char>)>,
+    pending: Vec<CorruptionEvent>,
+}
+
+impl CorruptionLog {
+    pub const fn new() -> Self {
+        CorruptionLog {
+            reported: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, event: CorruptionEvent) {
+        let key = (event.tag, event.slot);
+
+        if self.reported.contains(&key) || self.pending.len() >= MAX_CORRUPTION_EVENTS {
+            return;
+        }
+
+        self.reported.push(key);
+        self.pending.push(event);
+    }
+
+    /// Returns and clears the events not taken yet
+    pub fn take(&mut self) -> Vec<CorruptionEvent> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+impl Default for CorruptionLog {
+    fn default() -> Self {
+        CorruptionLog::new()
+    }
+}
+
+/// What `select` found in flash: the value to use, if any, and the copies that could not be read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selected<T> {
+    pub value: Option<T>,
+    pub corrupted: Vec<CorruptionEvent>,
+}
+
+/// Takes the newest good copy of the two slots, `slots[n]` is None when slot n was never written.
+/// The copy from before the A/B slots is only read when neither slot exists.
+pub fn select<T>(
+    tag: &'static str,
+    slots: [Option<Result<(u32, T), String>>; 2],
+    legacy: impl FnOnce() -> Option<Result<T, String>>,
+) -> Selected<T> {
+    let mut newest: Option<(u32, T)> = None;
+    let mut found = false;
+    let mut failures = Vec::new();
+
+    for (slot, read) in SLOTS.into_iter().zip(slots) {
+        match read {
+            None => continue,
+            Some(Ok((sequence, value))) => {
+                found = true;
+                let is_newer = match &newest {
+                    Some((newest, _)) => slot::is_newer(sequence, *newest),
+                    None => true,
+                };
+                if is_newer {
+                    newest = Some((sequence, value));
+                }
+            }
+            Some(Err(detail)) => {
+                found = true;
+                failures.push((Some(slot), detail));
+            }
+        }
+    }
+
+    if !found {
+        match legacy() {
+            Some(Ok(value)) => newest = Some((0, value)),
+            Some(Err(detail)) => failures.push((None, detail)),
+            None => (),
+        }
+    }
+
+    let recovered = newest.is_some();
+
+    Selected {
+        value: newest.map(|(_, value)| value),
+        corrupted: failures
+            .into_iter()
+            .map(|(slot, detail)| CorruptionEvent {
+                tag,
+                slot,
+                detail,
+                recovered,
+            })
+            .collect(),
+    }
+}
+
+impl Schema for NetworkCredentials {
+    const TAG: &'static str = NETWORK_TAG;
+    const VERSION: u16 = 1;
+    const MAX_SIZE: usize = 200;
+    const ENCRYPTED: bool = true;
+
+    fn migrations() -> &'static [Migration] {
+        &[from_legacy]
+    }
+}
+
+impl Schema for Device {
+    const TAG: &'static str = DEVICE_TAG;
+    const VERSION: u16 = 1;
+    const MAX_SIZE: usize = DEVICE_FLASH_LIMIT;
+
+    fn migrations() -> &'static [Migration] {
+        &[from_legacy]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    // Version 1 had only `a`, version 0 was the same without header
+    #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+    struct Sample {
+        a: u16,
+        b: u8,
+    }
+
+    #[derive(Serialize)]
+    struct SampleV1 {
+        a: u16,
+    }
+
+    fn add_b(mut payload: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        payload.push(7);
+        Ok(payload)
+    }
+
+    impl Schema for Sample {
+        const TAG: &'static str = "sample";
+        const VERSION: u16 = 2;
+        const MAX_SIZE: usize = 16;
+
+        fn migrations() -> &'static [Migration] {
+            &[from_legacy, add_b]
+        }
+    }
+
+    fn with_header(version: u16, payload: &[u8]) -> Vec<u8> {
+        let mut raw = MAGIC.to_vec();
+        raw.extend_from_slice(&version.to_le_bytes());
+        raw.extend_from_slice(payload);
+        raw
+    }
+
+    fn stored(sequence: u32, value: &Sample) -> Vec<u8> {
+        slot::encode_record(sequence, &encode(value).unwrap())
+    }
+
+    fn read(raw: &[u8]) -> Option<Result<(u32, Sample), String>> {
+        Some(decode_slot(raw, |value| Ok(value.to_vec())))
+    }
+
+    fn no_legacy() -> Option<Result<Sample, String>> {
+        None
+    }
+
+    #[test]
+    fn values_round_trip() {
+        let value = Sample { a: 300, b: 2 };
+        let raw = encode(&value).unwrap();
+
+        assert_eq!(split_header(&raw).0, Sample::VERSION);
+        assert_eq!(encoded_len(&value), Ok(raw.len()));
+        assert_eq!(decode::<Sample>(&raw), Ok(value));
+    }
+
+    #[test]
+    fn values_over_the_limit_are_refused() {
+        #[derive(Serialize, Deserialize)]
+        struct Big(Vec<u8>);
+
+        impl Schema for Big {
+            const TAG: &'static str = "big";
+            const VERSION: u16 = 1;
+            const MAX_SIZE: usize = 8;
+
+            fn migrations() -> &'static [Migration] {
+                &[from_legacy]
+            }
+        }
+
+        assert!(matches!(
+            encode(&Big(vec![0; 8])),
+            Err(StoreError::TooLarge(_, 8))
+        ));
+    }
+
+    #[test]
+    fn legacy_blobs_without_header_are_migrated() {
+        let legacy = bincode::serialize(&SampleV1 { a: 300 }).unwrap();
+
+        assert_eq!(split_header(&legacy), (LEGACY_VERSION, legacy.as_slice()));
+        assert_eq!(decode::<Sample>(&legacy), Ok(Sample { a: 300, b: 7 }));
+    }
+
+    #[test]
+    fn older_versions_run_the_remaining_migrations() {
+        let v1 = with_header(1, &bincode::serialize(&SampleV1 { a: 5 }).unwrap());
+
+        assert_eq!(decode::<Sample>(&v1), Ok(Sample { a: 5, b: 7 }));
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let v3 = with_header(3, &bincode::serialize(&Sample { a: 5, b: 1 }).unwrap());
+
+        assert_eq!(decode::<Sample>(&v3), Err(StoreError::NewerVersion(3)));
+    }
+
+    #[test]
+    fn a_newer_version_falls_back_to_the_other_slot() {
+        let old = Sample { a: 1, b: 1 };
+        let newer = slot::encode_record(
+            9,
+            &with_header(3, &bincode::serialize(&Sample { a: 2, b: 2 }).unwrap()),
+        );
+
+        let selected = select(
+            Sample::TAG,
+            [read(&stored(8, &old)), read(&newer)],
+            no_legacy,
+        );
+
+        assert_eq!(selected.value, Some(old));
+        assert_eq!(selected.corrupted.len(), 1);
+        assert_eq!(selected.corrupted[0].slot, Some('b'));
+        assert!(selected.corrupted[0].recovered);
+    }
+
+    #[test]
+    fn the_newest_good_slot_wins() {
+        let old = Sample { a: 1, b: 1 };
+        let new = Sample { a: 2, b: 2 };
+
+        let selected = select(
+            Sample::TAG,
+            [read(&stored(4, &new)), read(&stored(3, &old))],
+            no_legacy,
+        );
+
+        assert_eq!(selected.value, Some(new));
+        assert!(selected.corrupted.is_empty());
+    }
+
+    #[test]
+    fn a_corrupted_newer_slot_falls_back_to_the_older_one() {
+        let old = Sample { a: 1, b: 1 };
+        let mut new = stored(4, &Sample { a: 2, b: 2 });
+        let last = new.len() - 1;
+        new[last] ^= 0xff;
+
+        let selected = select(Sample::TAG, [read(&new), read(&stored(3, &old))], no_legacy);
+
+        assert_eq!(selected.value, Some(old));
+        assert_eq!(
+            selected.corrupted,
+            vec![CorruptionEvent {
+                tag: Sample::TAG,
+                slot: Some('a'),
+                detail: selected.corrupted[0].detail.clone(),
+                recovered: true,
+            }]
+        );
+        assert!(selected.corrupted[0]
+            .detail
+            .starts_with("checksum mismatch"));
+    }
+
+    #[test]
+    fn both_slots_bad_fall_back_to_the_default() {
+        let mut a = stored(1, &Sample { a: 1, b: 1 });
+        a[0] ^= 0xff;
+
+        let selected = select(Sample::TAG, [read(&a), read(&[1, 2, 3])], || {
+            panic!("legacy copies are not read when the slots exist")
+        });
+
+        assert_eq!(selected.value.unwrap_or(Sample { a: 0, b: 0 }).a, 0);
+        assert_eq!(selected.corrupted.len(), 2);
+        assert!(selected.corrupted.iter().all(|event| !event.recovered));
+    }
+
+    #[test]
+    fn the_legacy_copy_is_read_without_slots() {
+        let legacy = bincode::serialize(&SampleV1 { a: 3 }).unwrap();
+
+        let selected = select(Sample::TAG, [None, None], || {
+            Some(decode(&legacy).map_err(|err| err.to_string()))
+        });
+
+        assert_eq!(selected.value, Some(Sample { a: 3, b: 7 }));
+
+        let selected = select::<Sample>(Sample::TAG, [None, None], || {
+            Some(Err("broken".to_string()))
+        });
+
+        assert_eq!(selected.value, None);
+        assert_eq!(selected.corrupted[0].slot, None);
+    }
+
+    #[test]
+    fn corruption_is_reported_once_per_copy() {
+        let mut log = CorruptionLog::new();
+        let event = |slot| CorruptionEvent {
+            tag: "sample",
+            slot,
+            detail: String::new(),
+            recovered: false,
+        };
+
+        log.push(event(Some('a')));
+        log.push(event(Some('a')));
+        log.push(event(Some('b')));
+
+        assert_eq!(log.take().len(), 2);
+
+        log.push(event(Some('a')));
+        assert!(log.take().is_empty());
+    }
+
+    #[test]
+    fn pending_corruption_is_capped() {
+        let mut log = CorruptionLog::new();
+
+        for (index, tag) in ALL_TAGS.iter().enumerate() {
+            for slot in [Some('a'), Some('b'), None] {
+                log.push(CorruptionEvent {
+                    tag,
+                    slot,
+                    detail: index.to_string(),
+                    recovered: false,
+                });
+            }
+        }
+
+        assert_eq!(log.take().len(), MAX_CORRUPTION_EVENTS);
+    }
+}
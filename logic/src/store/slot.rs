@@ -0,0 +1,182 @@
+use std::fmt;
+
+// Sequence and checksum written before the value
+pub const RECORD_HEADER_LEN: usize = 8;
+// Every value is written alternately to two keys, a power loss can only break the one being written
+pub const SLOTS: [char; 2] = ['a', 'b'];
+
+pub fn slot_key(tag: &str, slot: char) -> String {
+    format!("{}.{}", tag, slot)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordError {
+    TooShort(usize),
+    Checksum(u32, u32),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordError::TooShort(len) => write!(f, "record has only {} bytes", len),
+            RecordError::Checksum(expected, found) => write!(
+                f,
+                "checksum mismatch, expected {:08x} found {:08x}",
+                expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub sequence: u32,
+    pub value: &'a [u8],
+}
+
+/// CRC-32 (IEEE), the one used by zip and ethernet
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+/// The checksum covers the sequence too, so a stale sequence cannot pass as a newer one
+pub fn encode_record(sequence: u32, value: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + value.len());
+    record.extend_from_slice(&sequence.to_le_bytes());
+    record.extend_from_slice(&[0; 4]);
+    record.extend_from_slice(value);
+
+    let crc = crc32_skipping_checksum(&record);
+    record[4..8].copy_from_slice(&crc.to_le_bytes());
+
+    record
+}
+
+pub fn decode_record(raw: &[u8]) -> Result<Record<'_>, RecordError> {
+    if raw.len() < RECORD_HEADER_LEN {
+        return Err(RecordError::TooShort(raw.len()));
+    }
+
+    let sequence = u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]);
+    let expected = u32::from_le_bytes([raw[4], raw[5], raw[6], raw[7]]);
+    let found = crc32_skipping_checksum(raw);
+
+    if expected != found {
+        return Err(RecordError::Checksum(expected, found));
+    }
+
+    Ok(Record {
+        sequence,
+        value: &raw[RECORD_HEADER_LEN..],
+    })
+}
+
+fn crc32_skipping_checksum(record: &[u8]) -> u32 {
+    let mut data = Vec::with_capacity(record.len() - 4);
+    data.extend_from_slice(&record[0..4]);
+    data.extend_from_slice(&record[RECORD_HEADER_LEN..]);
+
+    crc32(&data)
+}
+
+/// Whether `sequence` was written after `other`. Sequences wrap around, so this compares
+/// their distance instead of their value, like TCP sequence numbers.
+pub fn is_newer(sequence: u32, other: u32) -> bool {
+    (sequence.wrapping_sub(other) as i32) > 0
+}
+
+/// Picks the slot to write given the sequence of the good record in each one, if any.
+/// The newest good record is never the one overwritten.
+pub fn next_slot(sequences: [Option<u32>; 2]) -> (char, u32) {
+    match sequences {
+        [Some(a), Some(b)] if !is_newer(b, a) => (SLOTS[1], a.wrapping_add(1)),
+        [Some(_), Some(b)] => (SLOTS[0], b.wrapping_add(1)),
+        [Some(a), None] => (SLOTS[1], a.wrapping_add(1)),
+        [None, Some(b)] => (SLOTS[0], b.wrapping_add(1)),
+        [None, None] => (SLOTS[0], 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_the_ieee_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let raw = encode_record(7, b"value");
+
+        assert_eq!(raw.len(), RECORD_HEADER_LEN + 5);
+        assert_eq!(
+            decode_record(&raw),
+            Ok(Record {
+                sequence: 7,
+                value: b"value",
+            })
+        );
+    }
+
+    #[test]
+    fn damaged_records_are_refused() {
+        let mut raw = encode_record(7, b"value");
+        raw[RECORD_HEADER_LEN] ^= 1;
+        assert!(matches!(
+            decode_record(&raw),
+            Err(RecordError::Checksum(..))
+        ));
+
+        // The sequence is covered too
+        let mut raw = encode_record(7, b"value");
+        raw[0] = 8;
+        assert!(matches!(
+            decode_record(&raw),
+            Err(RecordError::Checksum(..))
+        ));
+
+        assert_eq!(decode_record(&[0; 7]), Err(RecordError::TooShort(7)));
+    }
+
+    #[test]
+    fn the_newest_slot_is_kept() {
+        assert_eq!(next_slot([None, None]), ('a', 1));
+        assert_eq!(next_slot([Some(1), None]), ('b', 2));
+        assert_eq!(next_slot([None, Some(4)]), ('a', 5));
+        assert_eq!(next_slot([Some(5), Some(4)]), ('b', 6));
+        assert_eq!(next_slot([Some(5), Some(6)]), ('a', 7));
+        assert_eq!(next_slot([Some(3), Some(3)]), ('b', 4));
+    }
+
+    #[test]
+    fn sequences_wrap_around() {
+        assert_eq!(next_slot([Some(u32::MAX), Some(u32::MAX - 1)]), ('b', 0));
+        // Slot b now holds the newest copy even though its sequence is smaller
+        assert_eq!(next_slot([Some(u32::MAX), Some(0)]), ('a', 1));
+        assert_eq!(next_slot([Some(1), Some(0)]), ('b', 2));
+
+        assert!(is_newer(0, u32::MAX));
+        assert!(!is_newer(u32::MAX, 0));
+        assert!(!is_newer(3, 3));
+    }
+
+    #[test]
+    fn slot_keys_fit_the_nvs_limit() {
+        assert_eq!(slot_key("client_cred", 'a'), "client_cred.a");
+    }
+}
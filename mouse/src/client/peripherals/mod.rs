@@ -1,3 +1,2 @@
 // TODO: if we can make both modules generics we can move this to common package
-pub mod button;
 pub mod stick;
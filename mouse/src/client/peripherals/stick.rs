@@ -1,9 +1,11 @@
 use std::{sync::Arc, time::Duration};
 
+use common::calibration;
 use esp_idf_hal::{
     adc::{self, *},
     gpio::{Gpio4, Gpio5},
 };
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
 use jojo_common::message::ClientMessage;
 use log::*;
 use parking_lot::{Condvar, Mutex};
@@ -16,8 +18,13 @@ pub struct StickTask {
     gpio_y: Gpio4,
     websocket_sender_tx: crossbeam_channel::Sender<jojo_common::message::ClientMessage>,
     wb_status: Arc<(Mutex<bool>, Condvar)>,
+    nvs_namespace: Arc<Mutex<EspNvs<NvsDefault>>>,
 }
 
+// Names of the zero reads in calibration::Calibration
+const X_INPUT: &str = "stick_x";
+const Y_INPUT: &str = "stick_y";
+
 impl StickTask {
     pub fn new(
         adc1: ADC1,
@@ -26,6 +33,7 @@ impl StickTask {
         // TODO: replace with stick_websocket_sender_tx and websocket Message Reads
         websocket_sender_tx: crossbeam_channel::Sender<jojo_common::message::ClientMessage>,
         wb_status: Arc<(Mutex<bool>, Condvar)>,
+        nvs_namespace: Arc<Mutex<EspNvs<NvsDefault>>>,
     ) -> Self {
         StickTask {
             adc1,
@@ -33,6 +41,7 @@ impl StickTask {
             gpio_y,
             websocket_sender_tx,
             wb_status,
+            nvs_namespace,
         }
     }
 }
@@ -169,6 +178,7 @@ pub fn init_task(task: StickTask) {
         gpio_y,
         websocket_sender_tx,
         wb_status,
+        nvs_namespace,
     } = task;
 
     info!("[stick_task]:creating");
@@ -195,15 +205,36 @@ pub fn init_task(task: StickTask) {
     loop {
         match main_state.state() {
             ReadStates::Calibrating => {
-                let x_zero: u16 = (0..10)
-                    .map(|_| adc_driver.read(&mut x_adc_channel).unwrap())
-                    .sum::<u16>()
-                    .div_ceil(10);
-
-                let y_zero: u16 = (0..10)
-                    .map(|_| adc_driver.read(&mut y_adc_channel).unwrap())
-                    .sum::<u16>()
-                    .div_ceil(10);
+                let mut stored = calibration::load(&nvs_namespace.lock());
+
+                // Imported or measured on an earlier boot, `calibration clear` measures it again
+                let (x_zero, y_zero) = match (stored.zero_read(X_INPUT), stored.zero_read(Y_INPUT))
+                {
+                    (Some(x_zero), Some(y_zero)) => {
+                        info!("[stick_task]: using stored calibration");
+                        (x_zero, y_zero)
+                    }
+                    _ => {
+                        let x_zero: u16 = (0..10)
+                            .map(|_| adc_driver.read(&mut x_adc_channel).unwrap())
+                            .sum::<u16>()
+                            .div_ceil(10);
+
+                        let y_zero: u16 = (0..10)
+                            .map(|_| adc_driver.read(&mut y_adc_channel).unwrap())
+                            .sum::<u16>()
+                            .div_ceil(10);
+
+                        stored.set_zero_read(X_INPUT, x_zero);
+                        stored.set_zero_read(Y_INPUT, y_zero);
+
+                        if let Err(err) = calibration::save(&mut nvs_namespace.lock(), &stored) {
+                            warn!("[stick_task]: cannot save calibration {:?}", err);
+                        }
+
+                        (x_zero, y_zero)
+                    }
+                };
 
                 let calibration = StickCalibration::calibrate(x_zero, y_zero);
 
@@ -1,3 +1,7 @@
+use std::io::Write;
+
+use flate2::{write::GzEncoder, Compression};
+
 fn main() {
     dotenv::dotenv().ok();
 
@@ -5,5 +9,25 @@ fn main() {
         println!("cargo:rustc-env={key}={value}");
     }
 
+    compress_ui();
+
     embuild::espidf::sysenv::output();
 }
+
+// The provisioning page is stored gzipped in flash and served as is with Content-Encoding: gzip
+fn compress_ui() {
+    let source = "ui/index.html";
+    println!("cargo:rerun-if-changed={source}");
+
+    let html = std::fs::read(source).expect("cannot read ui/index.html");
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&html).unwrap();
+
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    std::fs::write(
+        format!("{out_dir}/index.html.gz"),
+        encoder.finish().unwrap(),
+    )
+    .unwrap();
+}
@@ -0,0 +1,23 @@
+use esp_idf_svc::sys::{esp, esp_mac_type_t_ESP_MAC_WIFI_SOFTAP, esp_read_mac};
+
+const SSID_PREFIX: &str = "jojo";
+
+pub fn mac() -> anyhow::Result<[u8; 6]> {
+    let mut mac = [0u8; 6];
+
+    esp!(unsafe { esp_read_mac(mac.as_mut_ptr(), esp_mac_type_t_ESP_MAC_WIFI_SOFTAP) })?;
+
+    Ok(mac)
+}
+
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// SSID unique per device, built with the last two bytes of the MAC, e.g. `jojo-A1B2`
+pub fn ssid(mac: &[u8; 6]) -> String {
+    format!("{}-{:02X}{:02X}", SSID_PREFIX, mac[4], mac[5])
+}
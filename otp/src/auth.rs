@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use esp_idf_svc::{
+    http::server::{EspHttpConnection, Handler, Middleware, Request},
+    io::Write,
+};
+use log::*;
+use logic::http::ApiError;
+
+/// Rejects requests without the device token, does nothing when the device has no token stored.
+/// Browsers get a Basic challenge, any user name is accepted and the token goes as password.
+/// Scripts can send `Authorization: Bearer <token>` instead.
+#[derive(Debug, Clone)]
+pub struct AuthMiddleware {
+    token: Option<Arc<str>>,
+}
+
+impl AuthMiddleware {
+    pub fn new(token: Option<Arc<str>>) -> Self {
+        AuthMiddleware { token }
+    }
+}
+
+impl<'a, H> Middleware<EspHttpConnection<'a>, H> for AuthMiddleware
+where
+    H: Handler<EspHttpConnection<'a>, Error = anyhow::Error>,
+{
+    type Error = anyhow::Error;
+
+    fn handle(&self, connection: &mut EspHttpConnection<'a>, handler: &H) -> Result<(), Self::Error>
+    where
+        H: Handler<EspHttpConnection<'a>>,
+    {
+        let Some(token) = &self.token else {
+            return handler.handle(connection);
+        };
+
+        let req = Request::wrap(connection);
+
+        if authorized(req.header("Authorization"), token) {
+            return handler.handle(req.release());
+        }
+
+        warn!("[server_task]: unauthorized request to {}", req.uri());
+
+        let body = serde_json::to_string(&ApiError::unauthorized())?;
+
+        let mut response = req.into_response(
+            401,
+            None,
+            &[
+                ("Content-Type", "application/json"),
+                ("Cache-Control", "no-cache"),
+                ("WWW-Authenticate", "Basic realm=\"jojo\""),
+            ],
+        )?;
+
+        response.write_all(body.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+/// Checks an Authorization header value against the device token
+pub fn authorized(header: Option<&str>, token: &str) -> bool {
+    let Some((scheme, value)) = header.and_then(|header| header.trim().split_once(' ')) else {
+        return false;
+    };
+    let value = value.trim();
+
+    if scheme.eq_ignore_ascii_case("bearer") {
+        return constant_time_eq(value.as_bytes(), token.as_bytes());
+    }
+
+    if scheme.eq_ignore_ascii_case("basic") {
+        return STANDARD
+            .decode(value)
+            .ok()
+            .and_then(|decoded| String::from_utf8(decoded).ok())
+            .and_then(|credentials| {
+                credentials
+                    .split_once(':')
+                    .map(|(_, password)| constant_time_eq(password.as_bytes(), token.as_bytes()))
+            })
+            .unwrap_or(false);
+    }
+
+    false
+}
+
+// Compares every byte so the time taken does not tell how much of the token was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,54 @@
+use std::net::{Ipv4Addr, UdpSocket};
+use std::sync::Arc;
+
+use log::*;
+use logic::dns::answer;
+use parking_lot::{Condvar, Mutex};
+
+const DNS_PORT: u16 = 53;
+
+pub struct DnsTask {
+    wifi_status: Arc<(Mutex<bool>, Condvar)>,
+    ip: Ipv4Addr,
+}
+
+impl DnsTask {
+    pub fn new(wifi_status: Arc<(Mutex<bool>, Condvar)>, ip: Ipv4Addr) -> Self {
+        DnsTask { wifi_status, ip }
+    }
+}
+
+pub fn init_task(task: DnsTask) {
+    let DnsTask { wifi_status, ip } = task;
+
+    let (lock, cvar) = &*wifi_status;
+
+    let mut started = lock.lock();
+
+    if !*started {
+        cvar.wait(&mut started);
+    }
+    drop(started);
+
+    info!("[dns_task]:creating");
+
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DNS_PORT)).unwrap();
+
+    let mut buffer: [u8; 512] = [0; 512];
+
+    loop {
+        let (n_bytes, source) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(err) => {
+                warn!("[dns_task]: cannot read query {:?}", err);
+                continue;
+            }
+        };
+
+        if let Some(response) = answer(&buffer[0..n_bytes], ip) {
+            if let Err(err) = socket.send_to(&response, source) {
+                warn!("[dns_task]: cannot answer {:?}: {:?}", source, err);
+            }
+        }
+    }
+}
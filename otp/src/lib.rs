@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use common::led;
+use common::{console, led, secrets};
 use crossbeam_channel::unbounded;
 use esp_idf_hal::prelude::Peripherals;
 use esp_idf_svc::{
@@ -11,13 +11,15 @@ use log::*;
 use parking_lot::{Condvar, Mutex};
 use rgb::RGB8;
 
-pub mod nvs;
+pub mod access_point;
+pub mod auth;
+pub mod dns;
 pub mod server;
 pub mod wifi_otp;
 
 pub fn main(
     nvs_default: EspNvsPartition<NvsDefault>,
-    nvs_namespace: EspNvs<NvsDefault>,
+    mut nvs_namespace: EspNvs<NvsDefault>,
 ) -> anyhow::Result<()> {
     info!("[otp_task]: init");
 
@@ -30,12 +32,35 @@ pub fn main(
 
     neopixel.set(RGB8 { r: 0, g: 0, b: 0 })?;
 
-    let (wifi_scan_tx, wifi_scan_rx) = unbounded::<wifi_otp::ScanMessage>();
-    let (server_scan_tx, server_scan_rx) = unbounded::<wifi_otp::ScanMessage>();
-    let (nvs_tx, nvs_rx) = unbounded::<jojo_common::network::NetworkCredentials>();
+    let ap_ssid = access_point::ssid(&access_point::mac()?);
+    let ap_password = secrets::ap_password(&mut nvs_namespace)?;
+
+    // The secrets stay out of the logs, `secrets show` prints them on demand
+    info!(
+        "[otp_task]: access point ssid: {}, type secrets show in the console for its password",
+        ap_ssid
+    );
+
+    // Turned on per device from the console with `auth on`
+    let auth_token = secrets::stored_otp_token(&nvs_namespace);
+
+    if auth_token.is_none() {
+        warn!("[otp_task]: server auth is off, type auth on in the console");
+    }
+
+    // Blue means the device is waiting to be provisioned
+    neopixel.set(RGB8 { r: 0, g: 0, b: 20 })?;
+
+    let (wifi_tx, wifi_rx) = unbounded::<wifi_otp::WifiMessage>();
+    let (server_tx, server_rx) = unbounded::<wifi_otp::WifiMessage>();
 
     let wifi_status = Arc::new((Mutex::new(false), Condvar::new()));
     let wifi_status_server = Arc::clone(&wifi_status);
+    let wifi_status_dns = Arc::clone(&wifi_status);
+
+    let nvs_namespace = Arc::new(Mutex::new(nvs_namespace));
+    let nvs_namespace_server = Arc::clone(&nvs_namespace);
+    let nvs_namespace_console = Arc::clone(&nvs_namespace);
 
     info!("[otp_task]: creating tasks");
 
@@ -48,10 +73,10 @@ pub fn main(
                 sys_loop,
                 Some(nvs_default),
                 wifi_status,
-                "AP ESP TEST",
-                "Hello1234",
-                server_scan_tx,
-                wifi_scan_rx,
+                &ap_ssid,
+                &ap_password,
+                server_tx,
+                wifi_rx,
             ))
         })?;
 
@@ -61,17 +86,24 @@ pub fn main(
         .spawn(|| {
             server::init_task(server::ServerTask::new(
                 wifi_status_server,
-                wifi_scan_tx,
-                server_scan_rx,
-                nvs_tx,
+                wifi_tx,
+                server_rx,
+                nvs_namespace_server,
+                auth_token,
             ))
         })
         .unwrap();
 
-    let _nvs_thread = std::thread::Builder::new()
-        .name("nvs_thread".into())
-        .stack_size(7 * 1024)
-        .spawn(|| nvs::init_task(nvs::NvsTask::new(nvs_namespace, nvs_rx)))
+    let _dns_thread = std::thread::Builder::new()
+        .name("dns_thread".into())
+        .stack_size(4 * 1024)
+        .spawn(|| dns::init_task(dns::DnsTask::new(wifi_status_dns, wifi_otp::AP_IP)))
+        .unwrap();
+
+    let _console_thread = std::thread::Builder::new()
+        .name("console_thread".into())
+        .stack_size(6 * 1024)
+        .spawn(|| console::init_task(console::ConsoleTask::new("OTP", nvs_namespace_console)))
         .unwrap();
 
     Ok(())
@@ -2,49 +2,75 @@ use std::fmt::Debug;
 use std::sync::Arc;
 use std::time::Duration;
 
-use esp_idf_svc::http::Method;
+use common::{
+    bundle::{self, ConfigBundle},
+    device::DEVICE_FLASH_LIMIT,
+    network,
+    settings::{self, ServerSettings, SettingsUpdate},
+    store,
+};
+use esp_idf_svc::http::{Headers, Method};
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
 use esp_idf_svc::{
     http::server::{
         fn_handler, Configuration as HttpServerConfiguration, Connection, EspHttpConnection,
         EspHttpServer, Handler, Middleware, Request,
     },
-    io::{utils, Write},
+    io::{Read, Write},
 };
 use log::*;
+use logic::http::{self, ApiError};
+use logic::scan::{ScanEntry, ScanFilter};
 use parking_lot::{Condvar, Mutex};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::json;
 
-use super::wifi_otp;
+use super::{access_point, auth::AuthMiddleware, wifi_otp};
+
+// The wifi task scans before trying to connect, so this covers both plus the ip timeouts
+const VALIDATION_TIMEOUT: Duration = Duration::from_secs(40);
+// Delay before restarting so the response can reach the client
+const RESTART_DELAY: Duration = Duration::from_millis(500);
+// JSON takes more room than bincode, this lets a device close to the flash limit through
+const DEVICE_BODY_LIMIT: usize = 2 * DEVICE_FLASH_LIMIT;
+const SETTINGS_BODY_LIMIT: usize = 1024;
+const CONFIG_BODY_LIMIT: usize = DEVICE_BODY_LIMIT + 2 * 1024;
+// Escaped ssid and password at their max length plus the JSON around them
+const CREDENTIALS_BODY_LIMIT: usize = 512;
 
 pub struct ServerTask {
     wifi_status: Arc<(Mutex<bool>, Condvar)>,
-    wifi_tx: crossbeam_channel::Sender<wifi_otp::ScanMessage>,
-    server_rx: crossbeam_channel::Receiver<wifi_otp::ScanMessage>,
-    nvs_tx: crossbeam_channel::Sender<jojo_common::network::NetworkCredentials>,
+    wifi_tx: crossbeam_channel::Sender<wifi_otp::WifiMessage>,
+    server_rx: crossbeam_channel::Receiver<wifi_otp::WifiMessage>,
+    nvs_namespace: Arc<Mutex<EspNvs<NvsDefault>>>,
+    auth_token: Option<String>,
 }
 
 impl ServerTask {
     pub fn new(
         wifi_status: Arc<(Mutex<bool>, Condvar)>,
-        wifi_tx: crossbeam_channel::Sender<wifi_otp::ScanMessage>,
-        server_rx: crossbeam_channel::Receiver<wifi_otp::ScanMessage>,
-        nvs_tx: crossbeam_channel::Sender<jojo_common::network::NetworkCredentials>,
+        wifi_tx: crossbeam_channel::Sender<wifi_otp::WifiMessage>,
+        server_rx: crossbeam_channel::Receiver<wifi_otp::WifiMessage>,
+        nvs_namespace: Arc<Mutex<EspNvs<NvsDefault>>>,
+        auth_token: Option<String>,
     ) -> Self {
         ServerTask {
             wifi_status,
             wifi_tx,
             server_rx,
-            nvs_tx,
+            nvs_namespace,
+            auth_token,
         }
     }
 }
 
+/// Renders handler errors as JSON, an ApiError keeps its status and anything else is a 500
 #[derive(Debug)]
 pub struct ErrorMiddleware {}
 
 impl<'a, H> Middleware<EspHttpConnection<'a>, H> for ErrorMiddleware
 where
-    H: Handler<EspHttpConnection<'a>>,
+    H: Handler<EspHttpConnection<'a>, Error = anyhow::Error>,
 {
     type Error = anyhow::Error;
 
@@ -60,13 +86,18 @@ where
 
         if let Err(err) = handler.handle(connection) {
             if !connection.is_response_initiated() {
-                let mut resp = Request::wrap(connection).into_response(
-                    500,
-                    None,
-                    &[("Content-type", "application/json")],
-                )?;
-
-                write!(&mut resp, "ERROR: {err:?}")?;
+                let api_error = match err.downcast::<ApiError>() {
+                    Ok(api_error) => {
+                        warn!("[server_task]: {}", api_error);
+                        api_error
+                    }
+                    Err(err) => {
+                        error!("[server_task]: {:?}", err);
+                        ApiError::internal()
+                    }
+                };
+
+                write_json(Request::wrap(connection), api_error.status(), &api_error)?;
             } else {
                 // Nothing can be done as the error happened after the response was initiated, propagate further
                 Err(anyhow::Error::msg(format!("ERROR: {err:?}")))?;
@@ -77,9 +108,15 @@ where
     }
 }
 
-fn health(request: Request<&mut EspHttpConnection>) -> Result<(), anyhow::Error> {
+fn write_json<T: Serialize>(
+    request: Request<&mut EspHttpConnection>,
+    status: u16,
+    body: &T,
+) -> Result<(), anyhow::Error> {
+    let body = serde_json::to_string(body)?;
+
     let mut response = request.into_response(
-        200,
+        status,
         None,
         &[
             ("Content-Type", "application/json"),
@@ -87,68 +124,381 @@ fn health(request: Request<&mut EspHttpConnection>) -> Result<(), anyhow::Error>
         ],
     )?;
 
-    // TODO: make HealthResponse struct
-    let body = json!({ "status": "OK"});
-
-    response.write_all(body.to_string().as_bytes())?;
+    response.write_all(body.as_bytes())?;
 
     Ok(())
 }
 
-fn scan(
-    request: Request<&mut EspHttpConnection>,
-    wifi_tx: &crossbeam_channel::Sender<wifi_otp::ScanMessage>,
-    server_rx: &crossbeam_channel::Receiver<wifi_otp::ScanMessage>,
-) -> Result<(), anyhow::Error> {
+fn read_body(
+    request: &mut Request<&mut EspHttpConnection>,
+    limit: usize,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let content_len = request.content_len();
+
+    http::read_body(content_len, limit, |chunk| Ok(request.read(chunk)?))
+}
+
+fn read_json<T: DeserializeOwned>(
+    request: &mut Request<&mut EspHttpConnection>,
+    limit: usize,
+) -> Result<T, anyhow::Error> {
+    http::require_json(request.header("Content-Type"))?;
+
+    let body = read_body(request, limit)?;
+
+    Ok(http::parse_json(&body)?)
+}
+
+static INDEX_HTML_GZ: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/index.html.gz"));
+
+fn index(request: Request<&mut EspHttpConnection>) -> Result<(), anyhow::Error> {
     let mut response = request.into_response(
         200,
         None,
         &[
-            ("Content-Type", "application/json"),
+            ("Content-Type", "text/html; charset=utf-8"),
+            ("Content-Encoding", "gzip"),
             ("Cache-Control", "no-cache"),
         ],
     )?;
 
-    wifi_tx.try_send(wifi_otp::ScanMessage::Request).unwrap();
+    response.write_all(INDEX_HTML_GZ)?;
 
-    let mut buffer_scan_result: Vec<jojo_common::network::Ssid> = Vec::new();
+    Ok(())
+}
 
-    loop {
-        if let Ok(message) = server_rx.try_recv() {
-            if let wifi_otp::ScanMessage::Response(scan_result) = message {
-                buffer_scan_result.append(&mut scan_result.clone().try_into().unwrap());
-                break;
-            }
-            std::thread::sleep(Duration::from_millis(100));
-        };
-    }
+fn health(request: Request<&mut EspHttpConnection>) -> Result<(), anyhow::Error> {
+    // TODO: make HealthResponse struct
+    write_json(request, 200, &json!({ "status": "OK"}))
+}
+
+/// URLs the different OS use to detect a captive portal after joining a network
+const CAPTIVE_PORTAL_PROBES: [&str; 9] = [
+    "/generate_204",
+    "/gen_204",
+    "/hotspot-detect.html",
+    "/library/test/success.html",
+    "/connecttest.txt",
+    "/ncsi.txt",
+    "/redirect",
+    "/canonical.html",
+    "/success.txt",
+];
+
+fn captive_portal(request: Request<&mut EspHttpConnection>) -> Result<(), anyhow::Error> {
+    let location = format!("http://{}/", wifi_otp::AP_IP);
+
+    request.into_response(
+        302,
+        Some("Found"),
+        &[
+            ("Location", location.as_str()),
+            ("Cache-Control", "no-cache"),
+        ],
+    )?;
+
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct ScanResponse {
+    networks: Vec<ScanEntry>,
+}
+
+fn scan(
+    request: Request<&mut EspHttpConnection>,
+    wifi_tx: &crossbeam_channel::Sender<wifi_otp::WifiMessage>,
+    server_rx: &crossbeam_channel::Receiver<wifi_otp::WifiMessage>,
+) -> Result<(), anyhow::Error> {
+    let query = request.uri().split_once('?').map_or("", |(_, query)| query);
+
+    let filter =
+        ScanFilter::from_query(query).map_err(|err| ApiError::bad_request(err.to_string()))?;
+
+    // Drop responses left by requests that already gave up waiting
+    server_rx.try_iter().for_each(drop);
+
+    let timeout = filter.timeout();
+
+    wifi_tx.send(wifi_otp::WifiMessage::ScanRequest(filter))?;
+
+    let networks = loop {
+        match server_rx.recv_timeout(timeout) {
+            Ok(wifi_otp::WifiMessage::ScanResponse(networks)) => break networks,
+            Ok(_) => continue,
+            Err(_) => Err(ApiError::timeout("scan timed out"))?,
+        }
+    };
 
-    let body = jojo_common::otp::ScanResponse::new(buffer_scan_result);
+    let body = ScanResponse { networks };
 
     info!("[server_task]:Response:{:?}", body);
 
-    response.write_all(serde_json::to_string(&body)?.as_bytes())?;
+    write_json(request, 200, &body)
+}
 
-    Ok(())
+#[derive(Debug, Deserialize)]
+struct CredentialsRequest {
+    ssid: String,
+    // Open networks have no password
+    #[serde(default)]
+    password: String,
+}
+
+/// Maps a failed validation to the field the user has to fix
+fn validation_error(result: wifi_otp::ValidationResult) -> ApiError {
+    use wifi_otp::ValidationResult;
+
+    match result {
+        ValidationResult::Connected => ApiError::internal(),
+        ValidationResult::WrongPassword => ApiError::new(
+            422,
+            "wrong_password",
+            "cannot connect to the network with this password",
+        )
+        .with_field("password"),
+        ValidationResult::SsidNotFound => {
+            ApiError::new(422, "ssid_not_found", "network not found in range").with_field("ssid")
+        }
+        ValidationResult::Timeout => ApiError::timeout("network validation timed out"),
+        ValidationResult::Internal => ApiError::internal(),
+    }
 }
 
 fn save_credentials(
     mut request: Request<&mut EspHttpConnection>,
-    nvs_tx: &crossbeam_channel::Sender<jojo_common::network::NetworkCredentials>,
+    wifi_tx: &crossbeam_channel::Sender<wifi_otp::WifiMessage>,
+    server_rx: &crossbeam_channel::Receiver<wifi_otp::WifiMessage>,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+) -> Result<(), anyhow::Error> {
+    let CredentialsRequest { ssid, password } = read_json(&mut request, CREDENTIALS_BODY_LIMIT)?;
+
+    network::validate_credentials(&ssid, &password).map_err(ApiError::from)?;
+
+    let network_credentials = network::credentials(&ssid, &password)?;
+
+    // Drop responses left by requests that already gave up waiting
+    server_rx.try_iter().for_each(drop);
+
+    wifi_tx.send(wifi_otp::WifiMessage::ValidateRequest(network_credentials))?;
+
+    let (network_credentials, result) = loop {
+        match server_rx.recv_timeout(VALIDATION_TIMEOUT) {
+            Ok(wifi_otp::WifiMessage::ValidateResponse(credentials, result)) => {
+                break (Some(credentials), result)
+            }
+            Ok(_) => continue,
+            Err(_) => break (None, wifi_otp::ValidationResult::Timeout),
+        }
+    };
+
+    info!("[server_task]:validation result {:?}", result);
+
+    match network_credentials {
+        // Written before answering, so /finish finds them as soon as the client sees the success
+        Some(network_credentials) if result == wifi_otp::ValidationResult::Connected => {
+            store::save(&mut nvs_namespace.lock(), &network_credentials)?;
+        }
+        _ => Err(validation_error(result))?,
+    };
+
+    write_json(request, 200, &json!({ "saved": true, "result": result }))
+}
+
+#[derive(Debug, Serialize)]
+struct CredentialsSummary {
+    ssid: String,
+}
+
+#[derive(Debug, Serialize)]
+struct DeviceSummary {
+    id: String,
+    buttons: usize,
+    actions: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct StatusResponse {
+    firmware_version: &'static str,
+    mac: String,
+    credentials: Option<CredentialsSummary>,
+    device: Option<DeviceSummary>,
+}
+
+fn status(
+    request: Request<&mut EspHttpConnection>,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+) -> Result<(), anyhow::Error> {
+    let (credentials, device) = {
+        let nvs_namespace = nvs_namespace.lock();
+
+        let credentials = store::load::<jojo_common::network::NetworkCredentials>(&nvs_namespace)
+            .map(|credentials| CredentialsSummary {
+                ssid: credentials.ssid.to_string(),
+            });
+
+        let device = store::load::<jojo_common::device::Device>(&nvs_namespace).map(|device| {
+            DeviceSummary {
+                id: device.id().to_string(),
+                buttons: device.buttons().len(),
+                actions: device.actions_map().len(),
+            }
+        });
+
+        (credentials, device)
+    };
+
+    let body = StatusResponse {
+        firmware_version: env!("CARGO_PKG_VERSION"),
+        mac: access_point::format_mac(&access_point::mac()?),
+        credentials,
+        device,
+    };
+
+    write_json(request, 200, &body)
+}
+
+fn get_device(
+    request: Request<&mut EspHttpConnection>,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+) -> Result<(), anyhow::Error> {
+    let device = store::load::<jojo_common::device::Device>(&nvs_namespace.lock())
+        .ok_or_else(|| ApiError::not_found("no device stored"))?;
+
+    write_json(request, 200, &device)
+}
+
+fn put_device(
+    mut request: Request<&mut EspHttpConnection>,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
 ) -> Result<(), anyhow::Error> {
-    let (_, mut body) = request.split();
+    let device: jojo_common::device::Device = read_json(&mut request, DEVICE_BODY_LIMIT)?;
+
+    common::device::validate(&device).map_err(ApiError::from)?;
+
+    let size = store::encoded_len(&device)?;
+    if size > DEVICE_FLASH_LIMIT {
+        Err(ApiError::new(
+            413,
+            "device_too_large",
+            format!(
+                "device takes {} bytes, the limit is {}",
+                size, DEVICE_FLASH_LIMIT
+            ),
+        ))?;
+    }
+
+    info!("[server_task]: saving device in flash {:?}", device);
 
-    let mut buf = [0u8; 512];
-    let bytes_read = utils::try_read_full(&mut body, &mut buf).map_err(|e| e.0)?;
+    store::save(&mut nvs_namespace.lock(), &device)?;
 
-    let network_credentials: jojo_common::network::NetworkCredentials =
-        serde_json::from_slice(&buf[0..bytes_read])?;
+    write_json(request, 200, &device)
+}
 
-    nvs_tx.send(network_credentials)?;
+fn server_settings_body(settings: &ServerSettings) -> serde_json::Value {
+    // The auth key is a secret, only tell whether one is set
+    json!({
+        "websocket_path": settings.websocket_path(),
+        "broadcast_address": settings.broadcast_address(),
+        "broadcast_bind_address": settings.broadcast_bind_address(),
+        "auth_key_set": settings.auth_key().is_some(),
+    })
+}
 
-    // TODO: think a way to validate the nvs task has write flash, maybe a condvar or another channel
+fn get_server_settings(
+    request: Request<&mut EspHttpConnection>,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+) -> Result<(), anyhow::Error> {
+    let settings = settings::load(&nvs_namespace.lock());
 
-    request.into_ok_response()?;
+    write_json(request, 200, &server_settings_body(&settings))
+}
+
+/// Missing fields take the compile time value, a missing auth_key keeps the stored one
+fn put_server_settings(
+    mut request: Request<&mut EspHttpConnection>,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+) -> Result<(), anyhow::Error> {
+    let update: SettingsUpdate = read_json(&mut request, SETTINGS_BODY_LIMIT)?;
+
+    let mut nvs_namespace = nvs_namespace.lock();
+    let settings = update.apply(&settings::load(&nvs_namespace));
+
+    settings.validate().map_err(ApiError::from)?;
+
+    settings::save(&mut nvs_namespace, &settings)?;
+
+    write_json(request, 200, &server_settings_body(&settings))
+}
+
+fn get_config(
+    request: Request<&mut EspHttpConnection>,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+) -> Result<(), anyhow::Error> {
+    // `GET /config?secrets=true` adds the networks and the auth key
+    let query = request.uri().split_once('?').map_or("", |(_, query)| query);
+    let include_secrets = url::form_urlencoded::parse(query.as_bytes())
+        .any(|(key, value)| key == "secrets" && value == "true");
+
+    let bundle = bundle::export(&nvs_namespace.lock(), include_secrets)?;
+
+    write_json(request, 200, &bundle)
+}
+
+/// Sections missing in the bundle are kept, the device has to restart to use the new values
+fn put_config(
+    mut request: Request<&mut EspHttpConnection>,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+) -> Result<(), anyhow::Error> {
+    let bundle: ConfigBundle = read_json(&mut request, CONFIG_BODY_LIMIT)?;
+
+    bundle.validate().map_err(ApiError::from)?;
+
+    bundle::import(&mut nvs_namespace.lock(), &bundle)?;
+
+    write_json(
+        request,
+        200,
+        &json!({ "status": "imported", "restart_required": true }),
+    )
+}
+
+fn restart(request: Request<&mut EspHttpConnection>) -> Result<(), anyhow::Error> {
+    write_json(request, 200, &json!({ "status": "restarting" }))?;
+
+    common::restart_after(RESTART_DELAY);
+
+    Ok(())
+}
+
+/// Restarts into CLIENT mode, only allowed once credentials are in flash
+/// Sections the client cannot boot without, everything else falls back to a default
+fn missing_config(nvs_namespace: &EspNvs<NvsDefault>) -> Vec<&'static str> {
+    let mut missing = Vec::new();
+
+    if store::load::<jojo_common::network::NetworkCredentials>(nvs_namespace).is_none() {
+        missing.push("networks");
+    }
+
+    missing
+}
+
+fn finish(
+    request: Request<&mut EspHttpConnection>,
+    nvs_namespace: &Mutex<EspNvs<NvsDefault>>,
+) -> Result<(), anyhow::Error> {
+    let missing = missing_config(&nvs_namespace.lock());
+
+    if !missing.is_empty() {
+        Err(ApiError::conflict(format!(
+            "missing configuration: {}",
+            missing.join(", ")
+        )))?;
+    }
+
+    write_json(request, 200, &json!({ "status": "restarting" }))?;
+
+    common::restart_after(RESTART_DELAY);
 
     Ok(())
 }
@@ -158,9 +508,26 @@ pub fn init_task(task: ServerTask) {
         wifi_status,
         wifi_tx,
         server_rx,
-        nvs_tx,
+        nvs_namespace,
+        auth_token,
     } = task;
 
+    // The page, health and the captive portal probes stay open so phones can reach the portal,
+    // the API behind them needs the token when the device has one
+    let auth = AuthMiddleware::new(auth_token.map(Arc::from));
+
+    let credentials_nvs_namespace = Arc::clone(&nvs_namespace);
+    let status_nvs_namespace = Arc::clone(&nvs_namespace);
+    let get_device_nvs_namespace = Arc::clone(&nvs_namespace);
+    let put_device_nvs_namespace = Arc::clone(&nvs_namespace);
+    let get_settings_nvs_namespace = Arc::clone(&nvs_namespace);
+    let put_settings_nvs_namespace = Arc::clone(&nvs_namespace);
+    let get_config_nvs_namespace = Arc::clone(&nvs_namespace);
+    let put_config_nvs_namespace = Arc::clone(&nvs_namespace);
+
+    let scan_wifi_tx = wifi_tx.clone();
+    let scan_server_rx = server_rx.clone();
+
     let (lock, cvar) = &*wifi_status;
 
     let mut started = lock.lock();
@@ -173,6 +540,14 @@ pub fn init_task(task: ServerTask) {
     info!("[server_task]:creating");
     let mut server = EspHttpServer::new(&HttpServerConfiguration::default()).unwrap();
 
+    server
+        .handler(
+            "/",
+            Method::Get,
+            ErrorMiddleware {}.compose(fn_handler(index)),
+        )
+        .unwrap();
+
     server
         .handler(
             "/health",
@@ -185,9 +560,9 @@ pub fn init_task(task: ServerTask) {
         .handler(
             "/scan",
             Method::Get,
-            ErrorMiddleware {}.compose(fn_handler(move |request| {
-                scan(request, &wifi_tx, &server_rx)
-            })),
+            ErrorMiddleware {}.compose(auth.clone().compose(fn_handler(move |request| {
+                scan(request, &scan_wifi_tx, &scan_server_rx)
+            }))),
         )
         .unwrap();
 
@@ -195,13 +570,108 @@ pub fn init_task(task: ServerTask) {
         .handler(
             "/save_credentials",
             Method::Post,
-            ErrorMiddleware {}.compose(fn_handler(move |request| {
-                save_credentials(request, &nvs_tx)
-            })),
+            ErrorMiddleware {}.compose(auth.clone().compose(fn_handler(move |request| {
+                save_credentials(request, &wifi_tx, &server_rx, &credentials_nvs_namespace)
+            }))),
         )
         .unwrap();
 
-    // TODO: think about adding a restart endpoint
+    for probe in CAPTIVE_PORTAL_PROBES {
+        server
+            .handler(
+                probe,
+                Method::Get,
+                ErrorMiddleware {}.compose(fn_handler(captive_portal)),
+            )
+            .unwrap();
+    }
+
+    server
+        .handler(
+            "/status",
+            Method::Get,
+            ErrorMiddleware {}.compose(auth.clone().compose(fn_handler(move |request| {
+                status(request, &status_nvs_namespace)
+            }))),
+        )
+        .unwrap();
+
+    server
+        .handler(
+            "/device",
+            Method::Get,
+            ErrorMiddleware {}.compose(auth.clone().compose(fn_handler(move |request| {
+                get_device(request, &get_device_nvs_namespace)
+            }))),
+        )
+        .unwrap();
+
+    server
+        .handler(
+            "/device",
+            Method::Put,
+            ErrorMiddleware {}.compose(auth.clone().compose(fn_handler(move |request| {
+                put_device(request, &put_device_nvs_namespace)
+            }))),
+        )
+        .unwrap();
+
+    server
+        .handler(
+            "/server_settings",
+            Method::Get,
+            ErrorMiddleware {}.compose(auth.clone().compose(fn_handler(move |request| {
+                get_server_settings(request, &get_settings_nvs_namespace)
+            }))),
+        )
+        .unwrap();
+
+    server
+        .handler(
+            "/server_settings",
+            Method::Put,
+            ErrorMiddleware {}.compose(auth.clone().compose(fn_handler(move |request| {
+                put_server_settings(request, &put_settings_nvs_namespace)
+            }))),
+        )
+        .unwrap();
+
+    server
+        .handler(
+            "/config",
+            Method::Get,
+            ErrorMiddleware {}.compose(auth.clone().compose(fn_handler(move |request| {
+                get_config(request, &get_config_nvs_namespace)
+            }))),
+        )
+        .unwrap();
+
+    server
+        .handler(
+            "/config",
+            Method::Put,
+            ErrorMiddleware {}.compose(auth.clone().compose(fn_handler(move |request| {
+                put_config(request, &put_config_nvs_namespace)
+            }))),
+        )
+        .unwrap();
+
+    server
+        .handler(
+            "/restart",
+            Method::Post,
+            ErrorMiddleware {}.compose(auth.clone().compose(fn_handler(restart))),
+        )
+        .unwrap();
+
+    server
+        .handler(
+            "/finish",
+            Method::Post,
+            ErrorMiddleware {}
+                .compose(auth.compose(fn_handler(move |request| finish(request, &nvs_namespace)))),
+        )
+        .unwrap();
 
     loop {
         std::thread::sleep(Duration::from_millis(1000));
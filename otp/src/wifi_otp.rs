@@ -1,17 +1,30 @@
-use std::{sync::Arc, time::Duration};
+use std::{fmt, net::Ipv4Addr, sync::Arc, time::Duration};
 
+use common::network::Redacted;
 use esp_idf_hal::modem::Modem;
 use esp_idf_svc::{
     eventloop::{EspEventLoop, System},
+    ipv4::{self, Mask, RouterConfiguration, Subnet},
+    netif::{EspNetif, NetifConfiguration, NetifStack},
     nvs::{EspNvsPartition, NvsDefault},
     wifi::{
-        config::ScanConfig, AccessPointConfiguration, AuthMethod, BlockingWifi,
-        ClientConfiguration, Configuration, EspWifi,
+        config::ScanConfig, AccessPointConfiguration, AccessPointInfo, AuthMethod, BlockingWifi,
+        ClientConfiguration, Configuration, EspWifi, WifiDriver,
     },
 };
 use heapless::String;
 use log::*;
+use logic::scan::{dedup_networks, ScanEntry, ScanFilter};
 use parking_lot::{Condvar, Mutex};
+use serde::Serialize;
+
+use super::access_point;
+
+/// Address of the access point, also announced as DNS server to the stations so the captive portal works
+pub const AP_IP: Ipv4Addr = Ipv4Addr::new(192, 168, 71, 1);
+
+const VALIDATION_CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const VALIDATION_IP_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct ConnectTask<'a> {
     modem: Modem,
@@ -20,8 +33,8 @@ pub struct ConnectTask<'a> {
     status: Arc<(Mutex<bool>, Condvar)>,
     ssid: &'a str,
     password: &'a str,
-    tx_channel: crossbeam_channel::Sender<ScanMessage>,
-    rx_channel: crossbeam_channel::Receiver<ScanMessage>,
+    tx_channel: crossbeam_channel::Sender<WifiMessage>,
+    rx_channel: crossbeam_channel::Receiver<WifiMessage>,
 }
 
 impl<'a> ConnectTask<'a> {
@@ -32,8 +45,8 @@ impl<'a> ConnectTask<'a> {
         status: Arc<(Mutex<bool>, Condvar)>,
         ssid: &'a str,
         password: &'a str,
-        tx_channel: crossbeam_channel::Sender<ScanMessage>,
-        rx_channel: crossbeam_channel::Receiver<ScanMessage>,
+        tx_channel: crossbeam_channel::Sender<WifiMessage>,
+        rx_channel: crossbeam_channel::Receiver<WifiMessage>,
     ) -> Self {
         ConnectTask {
             modem,
@@ -48,6 +61,16 @@ impl<'a> ConnectTask<'a> {
     }
 }
 
+fn ap_configuration(ssid: &str, password: &str) -> AccessPointConfiguration {
+    AccessPointConfiguration {
+        ssid: String::try_from(ssid).unwrap(),
+        ssid_hidden: false,
+        auth_method: AuthMethod::WPA2Personal,
+        password: String::try_from(password).unwrap(),
+        ..Default::default()
+    }
+}
+
 fn connect(
     wifi: &mut BlockingWifi<EspWifi<'static>>,
     ssid: &str,
@@ -55,13 +78,7 @@ fn connect(
 ) -> anyhow::Result<()> {
     let wifi_configuration: Configuration = Configuration::Mixed(
         ClientConfiguration::default(),
-        AccessPointConfiguration {
-            ssid: String::try_from(ssid).unwrap(),
-            ssid_hidden: false,
-            auth_method: AuthMethod::WPA2Personal,
-            password: String::try_from(password).unwrap(),
-            ..Default::default()
-        },
+        ap_configuration(ssid, password),
     );
 
     wifi.set_configuration(&wifi_configuration)?;
@@ -72,44 +89,184 @@ fn connect(
     Ok(())
 }
 
-#[derive(Debug)]
-pub enum ScanMessage {
-    Request,
-    // TODO: find a way to wrap jojo_common::network::Ssid to work with heapless
-    Response(Vec<jojo_common::network::Ssid>),
+fn ap_netif() -> anyhow::Result<EspNetif> {
+    let netif = EspNetif::new_with_conf(&NetifConfiguration {
+        ip_configuration: ipv4::Configuration::Router(RouterConfiguration {
+            subnet: Subnet {
+                gateway: AP_IP,
+                mask: Mask(24),
+            },
+            dhcp_enabled: true,
+            dns: Some(AP_IP),
+            secondary_dns: Some(AP_IP),
+        }),
+        ..NetifConfiguration::wifi_default_router()
+    })?;
+
+    Ok(netif)
+}
+
+pub enum WifiMessage {
+    ScanRequest(ScanFilter),
+    ScanResponse(Vec<ScanEntry>),
+    ValidateRequest(jojo_common::network::NetworkCredentials),
+    ValidateResponse(jojo_common::network::NetworkCredentials, ValidationResult),
+}
+
+// Credentials are printed without their password
+impl fmt::Debug for WifiMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WifiMessage::ScanRequest(filter) => f.debug_tuple("ScanRequest").field(filter).finish(),
+            WifiMessage::ScanResponse(entries) => {
+                f.debug_tuple("ScanResponse").field(entries).finish()
+            }
+            WifiMessage::ValidateRequest(credentials) => f
+                .debug_tuple("ValidateRequest")
+                .field(&Redacted(credentials))
+                .finish(),
+            WifiMessage::ValidateResponse(credentials, result) => f
+                .debug_tuple("ValidateResponse")
+                .field(&Redacted(credentials))
+                .field(result)
+                .finish(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationResult {
+    Connected,
+    WrongPassword,
+    SsidNotFound,
+    Timeout,
+    /// The driver failed, nothing is known about the credentials
+    Internal,
+}
+
+fn scan_entry(network: &AccessPointInfo) -> ScanEntry {
+    ScanEntry {
+        ssid: network.ssid.to_string(),
+        bssid: access_point::format_mac(&network.bssid),
+        rssi: network.signal_strength,
+        channel: network.channel,
+        auth_method: network.auth_method.map(|method| format!("{:?}", method)),
+    }
 }
 
 fn scan(
     wifi: &mut BlockingWifi<EspWifi<'static>>,
-) -> anyhow::Result<(Vec<jojo_common::network::Ssid>, usize)> {
-    let mut vec_ssid: Vec<jojo_common::network::Ssid> = Vec::new();
+    filter: &ScanFilter,
+) -> anyhow::Result<Vec<ScanEntry>> {
+    let mut networks: Vec<ScanEntry> = Vec::new();
 
-    for channel in (1..=11).rev() {
+    for channel in filter.channels.clone() {
         wifi.wifi_mut().start_scan(
             &ScanConfig {
                 channel: Some(channel),
                 ..Default::default()
             },
-            false,
+            true,
         )?;
 
-        std::thread::sleep(Duration::from_millis(150));
-
         if let Ok(result) = wifi.wifi_mut().get_scan_result() {
-            result.into_iter().for_each(|network| {
-                if network.signal_strength > -50 {
-                    // TODO: find a way to wrap jojo_common::network::Ssid to work with heapless
-                    vec_ssid.push(network.ssid.to_string().try_into().unwrap());
-                }
-            });
+            networks.extend(
+                result
+                    .iter()
+                    .filter(|network| network.signal_strength >= filter.min_rssi)
+                    .map(scan_entry),
+            );
         }
     }
 
-    wifi.wifi_mut().stop_scan()?;
+    Ok(dedup_networks(networks))
+}
+
+/// Tries to join the network with the station side of the driver, the access point keeps running.
+/// The station goes back to its idle configuration whatever the outcome.
+fn validate(
+    wifi: &mut BlockingWifi<EspWifi<'static>>,
+    credentials: &jojo_common::network::NetworkCredentials,
+    ap_configuration: &AccessPointConfiguration,
+) -> ValidationResult {
+    let result = try_join(wifi, credentials, ap_configuration);
+
+    if let Err(err) = wifi.wifi_mut().disconnect() {
+        warn!("[connect_task]: cannot disconnect station {:?}", err);
+    }
+
+    if let Err(err) = wifi.set_configuration(&Configuration::Mixed(
+        ClientConfiguration::default(),
+        ap_configuration.clone(),
+    )) {
+        error!(
+            "[connect_task]: cannot restore the station configuration {:?}",
+            err
+        );
+    }
 
-    let n_aps = vec_ssid.len();
+    result.unwrap_or_else(|err| {
+        error!("[connect_task]: validation failed {:?}", err);
+        ValidationResult::Internal
+    })
+}
+
+/// The driver does not expose why an association failed, so a visible network that never associates
+/// is reported as a wrong password, and an association without an IP in time as a timeout.
+fn try_join(
+    wifi: &mut BlockingWifi<EspWifi<'static>>,
+    credentials: &jojo_common::network::NetworkCredentials,
+    ap_configuration: &AccessPointConfiguration,
+) -> anyhow::Result<ValidationResult> {
+    let ssid = credentials.ssid.to_string();
+    let password = credentials.password.to_string();
+
+    let visible = wifi
+        .scan()?
+        .iter()
+        .any(|network| network.ssid.as_str() == ssid);
+
+    if !visible {
+        return Ok(ValidationResult::SsidNotFound);
+    }
 
-    Ok((vec_ssid, n_aps))
+    // NOTE: the access point moves to the channel of the target network, stations may reconnect
+    wifi.set_configuration(&Configuration::Mixed(
+        ClientConfiguration {
+            ssid: String::try_from(ssid.as_str()).map_err(|_| anyhow::anyhow!("ssid too long"))?,
+            auth_method: if password.is_empty() {
+                AuthMethod::None
+            } else {
+                AuthMethod::WPA2Personal
+            },
+            password: String::try_from(password.as_str())
+                .map_err(|_| anyhow::anyhow!("password too long"))?,
+            ..Default::default()
+        },
+        ap_configuration.clone(),
+    ))?;
+
+    wifi.wifi_mut().connect()?;
+
+    let result = if wifi
+        .wifi_wait_while(
+            || wifi.is_connected().map(|connected| !connected),
+            Some(VALIDATION_CONNECT_TIMEOUT),
+        )
+        .is_err()
+    {
+        ValidationResult::WrongPassword
+    } else if wifi
+        .ip_wait_while(|| wifi.is_up().map(|up| !up), Some(VALIDATION_IP_TIMEOUT))
+        .is_err()
+    {
+        ValidationResult::Timeout
+    } else {
+        ValidationResult::Connected
+    };
+
+    Ok(result)
 }
 
 pub fn connect_task(task: ConnectTask) {
@@ -129,7 +286,12 @@ pub fn connect_task(task: ConnectTask) {
     let (lock, cvar) = &*status;
 
     let mut wifi_driver = BlockingWifi::wrap(
-        EspWifi::new(modem, sys_loop.clone(), nvs).unwrap(),
+        EspWifi::wrap_all(
+            WifiDriver::new(modem, sys_loop.clone(), nvs).unwrap(),
+            EspNetif::new(NetifStack::Sta).unwrap(),
+            ap_netif().unwrap(),
+        )
+        .unwrap(),
         sys_loop,
         // timer,
     )
@@ -143,18 +305,44 @@ pub fn connect_task(task: ConnectTask) {
 
     info!("[connect_task]:Start channel listening");
 
+    let ap_configuration = ap_configuration(ssid, password);
+
     loop {
         if let Ok(message) = rx_channel.try_recv() {
-            if let ScanMessage::Request = message {
-                info!("[connect_task]:message Request");
+            match message {
+                WifiMessage::ScanRequest(filter) => {
+                    info!("[connect_task]:message ScanRequest {:?}", filter);
+
+                    let scan_result = scan(&mut wifi_driver, &filter).unwrap_or_else(|err| {
+                        error!("[connect_task]: scan failed {:?}", err);
+                        vec![]
+                    });
+
+                    info!(
+                        "[connect_task]: {:?}, N: {:?}",
+                        scan_result,
+                        scan_result.len()
+                    );
 
-                let (scan_result, n_aps) = scan(&mut wifi_driver).unwrap();
+                    tx_channel
+                        .try_send(WifiMessage::ScanResponse(scan_result))
+                        .unwrap();
+                }
+                WifiMessage::ValidateRequest(credentials) => {
+                    info!(
+                        "[connect_task]:message ValidateRequest for {:?}",
+                        credentials.ssid
+                    );
+
+                    let result = validate(&mut wifi_driver, &credentials, &ap_configuration);
 
-                info!("[connect_task]: {:?}, N: {:?}", scan_result, n_aps);
+                    info!("[connect_task]: validation result {:?}", result);
 
-                tx_channel
-                    .try_send(ScanMessage::Response(scan_result))
-                    .unwrap();
+                    tx_channel
+                        .try_send(WifiMessage::ValidateResponse(credentials, result))
+                        .unwrap();
+                }
+                _ => warn!("[connect_task]: unexpected message {:?}", message),
             }
         }
 